@@ -1,4 +1,5 @@
-use core::fmt::Debug;
+use core::fmt::{self, Debug};
+use std::path::PathBuf;
 
 use thiserror::Error;
 
@@ -11,29 +12,82 @@ use bitcoin::{
   Txid, Transaction, BlockHash, Block,
 };
 
+/// Error code returned by the node when a transaction is already present in the blockchain.
+const RPC_VERIFY_ALREADY_IN_CHAIN: isize = -27;
+
+#[derive(Clone, Debug, Deserialize)]
+pub(crate) struct RpcErrorResponse {
+  code: isize,
+  message: String,
+}
+
 #[derive(Clone, Debug, Deserialize)]
 #[serde(untagged)]
 pub(crate) enum RpcResponse<T> {
   Ok { result: T },
-  Err { error: String },
+  Err { error: RpcErrorResponse },
 }
 
-#[derive(Clone, Debug)]
-pub struct Rpc(String);
+/// Authentication for the node's RPC.
+#[derive(Clone)]
+pub enum RpcAuth {
+  /// A username and password.
+  UserPass(String, String),
+  /// A path to the cookie file written by the node, which is re-read on every request.
+  CookieFile(PathBuf),
+}
+
+/// A RPC connection to a Bitcoin node.
+#[derive(Clone)]
+pub struct Rpc {
+  url: String,
+  auth: Option<RpcAuth>,
+}
+
+// Manually implemented so credentials are never written to logs
+impl Debug for Rpc {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("Rpc")
+      .field("url", &self.url)
+      .field("auth", &self.auth.as_ref().map(|_| "<redacted>"))
+      .finish()
+  }
+}
 
 #[derive(Clone, PartialEq, Eq, Debug, Error)]
 pub enum RpcError {
   #[error("couldn't connect to node")]
   ConnectionError,
-  #[error("request had an error: {0}")]
-  RequestError(String),
+  #[error("request had an error: {1}")]
+  RequestError(isize, String),
   #[error("node sent an invalid response")]
   InvalidResponse,
+  #[error("couldn't read the node's cookie file")]
+  InvalidCookie,
 }
 
 impl Rpc {
-  pub fn new(url: String) -> Rpc {
-    Rpc(url)
+  pub fn new(url: String, auth: Option<RpcAuth>) -> Rpc {
+    Rpc { url, auth }
+  }
+
+  pub(crate) fn request(
+    &self,
+    client: &reqwest::Client,
+    method: &str,
+    params: serde_json::Value,
+  ) -> Result<reqwest::RequestBuilder, RpcError> {
+    let request =
+      client.post(&self.url).json(&json!({ "jsonrpc": "2.0", "method": method, "params": params }));
+    Ok(match &self.auth {
+      None => request,
+      Some(RpcAuth::UserPass(user, pass)) => request.basic_auth(user, Some(pass)),
+      Some(RpcAuth::CookieFile(path)) => {
+        let cookie = std::fs::read_to_string(path).map_err(|_| RpcError::InvalidCookie)?;
+        let (user, pass) = cookie.trim().split_once(':').ok_or(RpcError::InvalidCookie)?;
+        request.basic_auth(user, Some(pass))
+      }
+    })
   }
 
   pub async fn rpc_call<Response: DeserializeOwned + Debug>(
@@ -42,9 +96,8 @@ impl Rpc {
     params: serde_json::Value,
   ) -> Result<Response, RpcError> {
     let client = reqwest::Client::new();
-    let res = client
-      .post(&self.0)
-      .json(&json!({ "jsonrpc": "2.0", "method": method, "params": params }))
+    let res = self
+      .request(&client, method, params)?
       .send()
       .await
       .map_err(|_| RpcError::ConnectionError)?
@@ -56,7 +109,7 @@ impl Rpc {
       serde_json::from_str(&res).map_err(|_| RpcError::InvalidResponse)?;
     match res {
       RpcResponse::Ok { result } => Ok(result),
-      RpcResponse::Err { error } => Err(RpcError::RequestError(error)),
+      RpcResponse::Err { error } => Err(RpcError::RequestError(error.code, error.message)),
     }
   }
 
@@ -74,7 +127,48 @@ impl Rpc {
     encode::deserialize(&bytes).map_err(|_| RpcError::InvalidResponse)
   }
 
+  /// Get a transaction by its hash. As no block hash is specified, this requires the node be run
+  /// with `-txindex` for transactions which have already been included in a block.
+  pub async fn get_transaction(&self, txid: &Txid) -> Result<Transaction, RpcError> {
+    let hex = self.rpc_call::<String>("getrawtransaction", json!([txid.to_hex(), false])).await?;
+    let bytes: Vec<u8> = FromHex::from_hex(&hex).map_err(|_| RpcError::InvalidResponse)?;
+    let tx: Transaction = encode::deserialize(&bytes).map_err(|_| RpcError::InvalidResponse)?;
+    // Don't trust the node to have returned the requested transaction
+    if tx.txid() != *txid {
+      Err(RpcError::InvalidResponse)?;
+    }
+    Ok(tx)
+  }
+
+  /// Get the hash of the block which included a transaction, if it has been included in one. This
+  /// requires the node be run with `-txindex`.
+  pub async fn get_transaction_block(&self, txid: &Txid) -> Result<Option<BlockHash>, RpcError> {
+    #[derive(Debug, Deserialize)]
+    struct Transaction {
+      blockhash: Option<BlockHash>,
+    }
+    let tx: Transaction = self.rpc_call("getrawtransaction", json!([txid.to_hex(), true])).await?;
+    Ok(tx.blockhash)
+  }
+
+  /// Get the median time past of a block, the median of the timestamps of it and the 10 blocks
+  /// before it.
+  pub async fn get_median_time_past(&self, block_hash: &BlockHash) -> Result<u64, RpcError> {
+    #[derive(Debug, Deserialize)]
+    struct Header {
+      mediantime: u64,
+    }
+    let header: Header =
+      self.rpc_call("getblockheader", json!([block_hash.to_hex(), true])).await?;
+    Ok(header.mediantime)
+  }
+
+  /// Publish a transaction. If the transaction is already in the blockchain, this is treated as
+  /// a success, making publication idempotent.
   pub async fn send_raw_transaction(&self, tx: &Transaction) -> Result<Txid, RpcError> {
-    self.rpc_call("sendrawtransaction", json!([encode::serialize_hex(tx)])).await
+    match self.rpc_call("sendrawtransaction", json!([encode::serialize_hex(tx)])).await {
+      Err(RpcError::RequestError(RPC_VERIFY_ALREADY_IN_CHAIN, _)) => Ok(tx.txid()),
+      res => res,
+    }
   }
 }
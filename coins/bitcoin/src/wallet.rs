@@ -3,6 +3,8 @@ use std::{
   collections::HashMap,
 };
 
+use thiserror::Error;
+
 use rand_core::RngCore;
 
 use transcript::{Transcript, RecommendedTranscript};
@@ -68,9 +70,27 @@ impl SpendableOutput {
   }
 }
 
+/// An error when creating a transaction.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Error)]
+pub enum TransactionError {
+  #[error("not enough funds for these payments")]
+  NotEnoughFunds,
+  #[error("amount overflowed")]
+  AmountOverflow,
+  #[error("replacement fee wasn't high enough to be relayed")]
+  FeeTooLow,
+}
+
 /// A signable transaction, clone-able across attempts.
+// The payments and change address are retained so the transaction can be rebuilt with a new fee
 #[derive(Clone, Debug)]
-pub struct SignableTransaction(Transaction, Vec<Scalar>, Vec<TxOut>);
+pub struct SignableTransaction(
+  Transaction,
+  Vec<Scalar>,
+  Vec<TxOut>,
+  Vec<(Address, u64)>,
+  Option<Address>,
+);
 
 impl SignableTransaction {
   fn calculate_weight(inputs: usize, payments: &[(Address, u64)], change: Option<&Address>) -> u64 {
@@ -103,35 +123,48 @@ impl SignableTransaction {
     payments: &[(Address, u64)],
     change: Option<Address>,
     fee: u64,
-  ) -> Option<SignableTransaction> {
-    let input_sat = inputs.iter().map(|input| input.output.value).sum::<u64>();
+  ) -> Result<SignableTransaction, TransactionError> {
+    let input_sat = inputs
+      .iter()
+      .try_fold(0u64, |sum, input| sum.checked_add(input.output.value))
+      .ok_or(TransactionError::AmountOverflow)?;
     let offsets = inputs.iter().map(|input| input.offset).collect();
     let tx_ins = inputs
       .iter()
       .map(|input| TxIn {
         previous_output: input.outpoint,
         script_sig: Script::new(),
-        sequence: Sequence::MAX,
+        // Signal replaceability, so the fee can be bumped
+        sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
         witness: Witness::new(),
       })
       .collect::<Vec<_>>();
 
-    let payment_sat = payments.iter().map(|payment| payment.1).sum::<u64>();
+    let payment_sat = payments
+      .iter()
+      .try_fold(0u64, |sum, payment| sum.checked_add(payment.1))
+      .ok_or(TransactionError::AmountOverflow)?;
     let mut tx_outs = payments
       .iter()
       .map(|payment| TxOut { value: payment.1, script_pubkey: payment.0.script_pubkey() })
       .collect::<Vec<_>>();
 
-    let actual_fee = fee * Self::calculate_weight(tx_ins.len(), payments, None);
-    if payment_sat > (input_sat - actual_fee) {
-      return None;
+    let actual_fee = fee
+      .checked_mul(Self::calculate_weight(tx_ins.len(), payments, None))
+      .ok_or(TransactionError::AmountOverflow)?;
+    let spent = payment_sat.checked_add(actual_fee).ok_or(TransactionError::AmountOverflow)?;
+    if spent > input_sat {
+      Err(TransactionError::NotEnoughFunds)?;
     }
 
     // If there's a change address, check if there's a meaningful change
     if let Some(change) = change.as_ref() {
-      let fee_with_change = fee * Self::calculate_weight(tx_ins.len(), payments, Some(change));
       // If there's a non-zero change, add it
-      if let Some(value) = input_sat.checked_sub(payment_sat + fee_with_change) {
+      if let Some(value) = fee
+        .checked_mul(Self::calculate_weight(tx_ins.len(), payments, Some(change)))
+        .and_then(|fee_with_change| payment_sat.checked_add(fee_with_change))
+        .and_then(|spent| input_sat.checked_sub(spent))
+      {
         tx_outs.push(TxOut { value, script_pubkey: change.script_pubkey() });
       }
     }
@@ -139,13 +172,83 @@ impl SignableTransaction {
     // TODO: Drop outputs which BTC will consider spam (outputs worth less than the cost to spend
     // them)
 
-    Some(SignableTransaction(
+    Ok(SignableTransaction(
       Transaction { version: 2, lock_time: PackedLockTime::ZERO, input: tx_ins, output: tx_outs },
       offsets,
       inputs.drain(..).map(|input| input.output).collect(),
+      payments.to_vec(),
+      change,
     ))
   }
 
+  /// The fee this transaction pays.
+  pub fn fee(&self) -> u64 {
+    // new ensures the inputs' sum doesn't overflow and covers the outputs, so this won't panic
+    let sum = |outputs: &[TxOut]| {
+      outputs.iter().try_fold(0u64, |sum, output| sum.checked_add(output.value))
+    };
+    sum(&self.2)
+      .zip(sum(&self.0.output))
+      .and_then(|(inputs, outputs)| inputs.checked_sub(outputs))
+      .expect("transaction spent more than its inputs")
+  }
+
+  /// The weight this transaction will have once signed. As Taproot key-path spends have a
+  /// fixed-size signature, this is exact.
+  pub fn signed_weight(&self) -> usize {
+    let mut tx = self.0.clone();
+    for input in tx.input.iter_mut() {
+      // Signatures use SIGHASH_DEFAULT, which isn't encoded, making them 64 bytes
+      input.witness = Witness::from_vec(vec![vec![0; 64]]);
+    }
+    tx.weight()
+  }
+
+  // The virtual size this transaction will have once signed
+  fn signed_vsize(&self) -> u64 {
+    u64::try_from((self.signed_weight() + 3) / 4).unwrap()
+  }
+
+  /// Check if this transaction can replace the original under BIP-125. This requires it pay a
+  /// higher absolute fee (rule 3) by at least the minimum relay fee for its own size (rule 4).
+  /// The minimum relay fee is specified in satoshis per virtual byte.
+  pub fn can_replace(&self, original: &SignableTransaction, min_relay_fee: u64) -> bool {
+    self
+      .signed_vsize()
+      .checked_mul(min_relay_fee)
+      .and_then(|relay_fee| original.fee().checked_add(relay_fee))
+      .map(|required| self.fee() >= required)
+      .unwrap_or(false)
+  }
+
+  /// Rebuild this transaction, with the same inputs and payments, paying the specified fee per
+  /// weight unit. Errors with FeeTooLow if the result couldn't replace this transaction under
+  /// BIP-125, as checked by `can_replace`.
+  pub fn bump_fee(
+    &self,
+    fee: u64,
+    min_relay_fee: u64,
+  ) -> Result<SignableTransaction, TransactionError> {
+    let inputs = self
+      .0
+      .input
+      .iter()
+      .zip(&self.1)
+      .zip(&self.2)
+      .map(|((input, offset), output)| SpendableOutput {
+        offset: *offset,
+        output: output.clone(),
+        outpoint: input.previous_output,
+      })
+      .collect();
+
+    let replacement = SignableTransaction::new(inputs, &self.3, self.4.clone(), fee)?;
+    if !replacement.can_replace(self, min_relay_fee) {
+      Err(TransactionError::FeeTooLow)?;
+    }
+    Ok(replacement)
+  }
+
   /// Create a multisig machine for this transaction.
   pub async fn multisig(
     self,
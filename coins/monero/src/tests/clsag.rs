@@ -2,10 +2,14 @@ use core::ops::Deref;
 #[cfg(feature = "multisig")]
 use std::sync::{Arc, RwLock};
 
+use hex_literal::hex;
 use zeroize::Zeroizing;
-use rand_core::{RngCore, OsRng};
+use rand_core::{RngCore, SeedableRng, OsRng};
+use rand_chacha::ChaCha20Rng;
 
-use curve25519_dalek::{constants::ED25519_BASEPOINT_TABLE, scalar::Scalar};
+use curve25519_dalek::{
+  constants::ED25519_BASEPOINT_TABLE, scalar::Scalar, edwards::CompressedEdwardsY,
+};
 
 #[cfg(feature = "multisig")]
 use transcript::{Transcript, RecommendedTranscript};
@@ -29,7 +33,6 @@ use frost::tests::{key_gen, algorithm_machines, sign};
 const RING_LEN: u64 = 11;
 const AMOUNT: u64 = 1337;
 
-#[cfg(feature = "multisig")]
 const RING_INDEX: u8 = 3;
 
 #[test]
@@ -77,6 +80,83 @@ fn clsag() {
   }
 }
 
+// Signing with a seeded RNG, over a ring and secrets derived from that same seed, must produce
+// this exact, valid signature
+#[test]
+fn clsag_deterministic() {
+  let scalar = |scalar| Scalar::from_canonical_bytes(scalar).unwrap();
+  let point = |point| CompressedEdwardsY(point).decompress().unwrap();
+
+  let sign = || {
+    let mut rng = ChaCha20Rng::from_seed([0xff; 32]);
+    let msg = [1; 32];
+
+    let dest = Zeroizing::new(random_scalar(&mut rng));
+    let mask = random_scalar(&mut rng);
+    let mut ring = vec![];
+    for i in 0 .. RING_LEN {
+      if i == u64::from(RING_INDEX) {
+        ring.push([
+          dest.deref() * &ED25519_BASEPOINT_TABLE,
+          Commitment::new(mask, AMOUNT).calculate(),
+        ]);
+      } else {
+        ring.push([
+          &random_scalar(&mut rng) * &ED25519_BASEPOINT_TABLE,
+          Commitment::new(random_scalar(&mut rng), rng.next_u64()).calculate(),
+        ]);
+      }
+    }
+
+    let image = generate_key_image(&dest);
+    let sum_outputs = random_scalar(&mut rng);
+    let (clsag, pseudo_out) = Clsag::sign(
+      &mut rng,
+      vec![(
+        dest,
+        image,
+        ClsagInput::new(
+          Commitment::new(mask, AMOUNT),
+          Decoys { i: RING_INDEX, offsets: (1 ..= RING_LEN).collect(), ring: ring.clone() },
+        )
+        .unwrap(),
+      )],
+      sum_outputs,
+      msg,
+    )
+    .swap_remove(0);
+    clsag.verify(&ring, &image, &pseudo_out, &msg).unwrap();
+    (clsag, pseudo_out)
+  };
+
+  let (clsag, pseudo_out) = sign();
+  assert_eq!(
+    clsag,
+    Clsag {
+      D: point(hex!("955bc74b74cdf52191b0c13dafe87628370422d379f003ba343cd72769e4b2aa")),
+      s: vec![
+        scalar(hex!("c08850a3b9ed35716d04dbc9f4e81c2e5ff90750dfc155df06dbab57a67c1e0c")),
+        scalar(hex!("322062b789350e3022a094bf52ff66b515e72c5d5dd644eb4014db78f0a15908")),
+        scalar(hex!("115562db90800f88d15538c294cd85128852b0e69e9bb05ccfbbbdc928fb3809")),
+        scalar(hex!("2cce13c8d543dc4f04cd09ab325b02efd38335f447ac114385205683223e5b09")),
+        scalar(hex!("9f3332d5c5443000ec0fedb98e79ba25c2b6d8ec2370dc36f0500141453ae20f")),
+        scalar(hex!("38ec1466e66a78c9da9c78ec48e2d682e4c84753fae30cc9d6d23c6df766aa0a")),
+        scalar(hex!("e4e0a07eafa612290746788fae8734a75b2670e9f3a98889eb309b9d1999010e")),
+        scalar(hex!("aa00aaff40ada122866ebc7fe51c660e7a093a634b0f1feda32493be8a200701")),
+        scalar(hex!("3c9276b65a992fa77b668193f3e3eceba542627f1cb8d7f8127db61a32d65608")),
+        scalar(hex!("5349ee4f2fb927ccdc7e8b6ccd62c5777483968ff957ed08cb65b3190ec2e107")),
+        scalar(hex!("9c278852bac836fe4e7cbc4358e67ea9fde0ac16cf96b4fc29101d8e1fbf7f07")),
+      ],
+      c1: scalar(hex!("8bafbbf651b32a8d13daa955bf551f4f4bfda3fe11865c87e966e7d4ebc1ac0e")),
+    }
+  );
+  assert_eq!(
+    pseudo_out,
+    point(hex!("e5441184c6a1eb6036579010c982aaa7d44dd9740c475ee478171d1f78bf80cd"))
+  );
+  assert_eq!((clsag, pseudo_out), sign());
+}
+
 #[cfg(feature = "multisig")]
 #[test]
 fn clsag_multisig() {
@@ -1,10 +1,21 @@
-use std::{io, collections::HashMap};
+use std::{
+  str::FromStr,
+  io::{self, Read},
+  ops::Range,
+  time::Duration,
+  collections::HashMap,
+};
 
 use async_trait::async_trait;
 
+use log::{debug, warn};
+
 #[rustfmt::skip]
 use bitcoin::{
-  hashes::Hash, schnorr::TweakedPublicKey, OutPoint, Transaction, Block, Network, Address
+  hashes::Hash,
+  schnorr::TweakedPublicKey,
+  util::{address::Payload, merkleblock::{MerkleBlock, PartialMerkleTree}},
+  OutPoint, Transaction, Block, BlockHeader, BlockHash, Network, Address
 };
 
 #[cfg(test)]
@@ -24,11 +35,16 @@ use frost::{curve::Secp256k1, ThresholdKeys};
 
 use bitcoin_serai::{
   crypto::{x_only, make_even},
-  wallet::{SpendableOutput, TransactionMachine, SignableTransaction as BSignableTransaction},
-  rpc::Rpc,
+  wallet::{
+    SpendableOutput, TransactionError, TransactionMachine,
+    SignableTransaction as BSignableTransaction,
+  },
+  rpc::{RpcAuth, RpcError, Rpc},
 };
 
-use crate::coin::{CoinError, Block as BlockTrait, OutputType, Output as OutputTrait, Coin};
+use crate::coin::{
+  CoinError, Amount, Block as BlockTrait, OutputType, Output as OutputTrait, Coin, bind_transcript,
+};
 
 impl BlockTrait for Block {
   type Id = [u8; 32];
@@ -42,6 +58,9 @@ pub struct Fee(u64);
 
 #[derive(Clone, Debug)]
 pub struct Output(SpendableOutput, OutputType);
+
+/// A merkle proof of a transaction's inclusion in a block, as used by SPV clients.
+pub type MerkleProof = PartialMerkleTree;
 impl OutputTrait for Output {
   type Id = [u8; 36];
 
@@ -53,8 +72,8 @@ impl OutputTrait for Output {
     self.0.id()
   }
 
-  fn amount(&self) -> u64 {
-    self.0.output.value
+  fn amount(&self) -> Amount {
+    Amount(self.0.output.value)
   }
 
   fn serialize(&self) -> Vec<u8> {
@@ -68,11 +87,68 @@ impl OutputTrait for Output {
   }
 }
 
+impl Output {
+  /// Serialize a set of outputs. Each is length-prefixed so a corrupt record can be skipped
+  /// without aborting reading the rest.
+  pub fn serialize_many(outputs: &[Output]) -> Vec<u8> {
+    let mut res = u32::try_from(outputs.len()).unwrap().to_le_bytes().to_vec();
+    for output in outputs {
+      let serialized = output.serialize();
+      res.extend(u32::try_from(serialized.len()).unwrap().to_le_bytes());
+      res.extend(serialized);
+    }
+    res
+  }
+
+  /// Read a set of outputs serialized with `serialize_many`. Corrupt records are skipped, as are
+  /// any records missing due to the set being partially written. Returns the outputs read and the
+  /// amount of records skipped.
+  pub fn read_many<R: Read>(reader: &mut R) -> io::Result<(Vec<Output>, usize)> {
+    let mut count = [0; 4];
+    reader.read_exact(&mut count)?;
+    let count = usize::try_from(u32::from_le_bytes(count)).unwrap();
+
+    let mut outputs = vec![];
+    let mut skipped = 0;
+    for i in 0 .. count {
+      let mut len = [0; 4];
+      let mut record = vec![];
+      let complete = reader.read_exact(&mut len).is_ok() && {
+        let len = u32::from_le_bytes(len);
+        reader.by_ref().take(len.into()).read_to_end(&mut record)? == usize::try_from(len).unwrap()
+      };
+      if !complete {
+        warn!("output set was truncated after {} records", i);
+        return Ok((outputs, skipped + (count - i)));
+      }
+
+      // Require the entire record be consumed, so trailing garbage is also detected
+      let mut slice = record.as_slice();
+      match Output::read(&mut slice) {
+        Ok(output) if slice.is_empty() => outputs.push(output),
+        _ => {
+          warn!("skipping corrupt output record {}", i);
+          skipped += 1;
+        }
+      }
+    }
+    Ok((outputs, skipped))
+  }
+}
+
 #[derive(Debug)]
 pub struct SignableTransaction {
   keys: ThresholdKeys<Secp256k1>,
-  transcript: RecommendedTranscript,
-  actual: BSignableTransaction,
+  pub(crate) transcript: RecommendedTranscript,
+  pub(crate) actual: BSignableTransaction,
+}
+
+fn transaction_error(e: TransactionError) -> CoinError {
+  match e {
+    TransactionError::NotEnoughFunds => CoinError::NotEnoughFunds,
+    TransactionError::AmountOverflow => CoinError::AmountOverflow,
+    TransactionError::FeeTooLow => CoinError::FeeTooLow,
+  }
 }
 
 fn next_key(mut key: ProjectivePoint, i: usize) -> (ProjectivePoint, Scalar) {
@@ -88,22 +164,259 @@ fn next_key(mut key: ProjectivePoint, i: usize) -> (ProjectivePoint, Scalar) {
   (key, offset)
 }
 
+// Total supply of Bitcoin, in satoshis, which no valid output can exceed
+const MAX_MONEY: u64 = 21_000_000 * 100_000_000;
+
 fn branch(key: ProjectivePoint) -> (ProjectivePoint, Scalar) {
   next_key(key, 1)
 }
 
-fn change(key: ProjectivePoint) -> (ProjectivePoint, Scalar) {
-  next_key(key, 2)
+// Change keys are rotated between, with the index selecting which
+fn change(key: ProjectivePoint, index: usize) -> (ProjectivePoint, Scalar) {
+  next_key(key, 2 + index)
+}
+
+/// Policy for when a block is considered confirmed.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ConfirmationPolicy {
+  /// The block, and the amount of blocks minus one on top of it, exist.
+  Blocks(usize),
+  /// The tip's median time past is at least this far past the block's median time past.
+  Time(Duration),
 }
 
 #[derive(Clone, Debug)]
 pub struct Bitcoin {
   pub(crate) rpc: Rpc,
+  max_inputs: usize,
+  max_outputs: usize,
+  confirmation_policy: ConfirmationPolicy,
+  network: Network,
+  scan_from: usize,
+  change_addresses: usize,
 }
 
 impl Bitcoin {
-  pub async fn new(url: String) -> Bitcoin {
-    Bitcoin { rpc: Rpc::new(url) }
+  /// Maximum depth of a reorganization which will be followed. A reorganization deeper than this
+  /// undoes blocks which were long confirmed, indicating something catastrophic.
+  pub const MAX_REORG_DEPTH: usize = <Self as Coin>::CONFIRMATIONS + 3;
+
+  pub async fn new(url: String, auth: Option<RpcAuth>) -> Bitcoin {
+    Bitcoin {
+      rpc: Rpc::new(url, auth),
+      max_inputs: <Self as Coin>::MAX_INPUTS,
+      max_outputs: <Self as Coin>::MAX_OUTPUTS,
+      confirmation_policy: ConfirmationPolicy::Blocks(<Self as Coin>::CONFIRMATIONS),
+      network: Network::Regtest,
+      scan_from: 0,
+      change_addresses: 1,
+    }
+  }
+
+  /// Override the maximum amount of inputs a transaction may have.
+  pub fn with_max_inputs(mut self, max_inputs: usize) -> Bitcoin {
+    assert!(max_inputs != 0, "transactions must be allowed at least one input");
+    self.max_inputs = max_inputs;
+    self
+  }
+
+  /// Override the maximum amount of outputs a transaction may have.
+  pub fn with_max_outputs(mut self, max_outputs: usize) -> Bitcoin {
+    assert!(max_outputs != 0, "transactions must be allowed at least one output");
+    self.max_outputs = max_outputs;
+    self
+  }
+
+  /// Override the policy for when blocks are considered confirmed.
+  pub fn with_confirmation_policy(mut self, policy: ConfirmationPolicy) -> Bitcoin {
+    assert!(policy != ConfirmationPolicy::Blocks(0), "blocks must have at least one confirmation");
+    self.confirmation_policy = policy;
+    self
+  }
+
+  /// Override the network addresses are for, which defaults to regtest.
+  pub fn with_network(mut self, network: Network) -> Bitcoin {
+    self.network = network;
+    self
+  }
+
+  /// Override the block scanning starts from, such as the block the keys were generated at.
+  pub fn with_scan_from(mut self, scan_from: usize) -> Bitcoin {
+    self.scan_from = scan_from;
+    self
+  }
+
+  /// Override the amount of change addresses rotated between, which defaults to one. Each
+  /// transaction sends its change to the address selected by the change index it's prepared with,
+  /// which is advanced per transaction. Every change address is scanned for.
+  pub fn with_change_addresses(mut self, change_addresses: usize) -> Bitcoin {
+    assert!(change_addresses != 0, "there must be at least one change address");
+    self.change_addresses = change_addresses;
+    self
+  }
+
+  /// Range of blocks which may currently be scanned for outputs. This starts from the configured
+  /// block to scan from and ends with the latest confirmed block, per the confirmation policy.
+  pub async fn scan_range(&self) -> Result<Range<usize>, CoinError> {
+    let end = self.get_confirmed_block_number().await?.map(|confirmed| confirmed + 1).unwrap_or(0);
+    Ok(self.scan_from .. end.max(self.scan_from))
+  }
+
+  /// Parse an address, checking it's for the network this coin is configured for.
+  pub fn parse_address(&self, address: &str) -> Result<Address, CoinError> {
+    let address = Address::from_str(address).map_err(|_| CoinError::InvalidAddress)?;
+    let valid = match (address.network, self.network) {
+      (parsed, network) if parsed == network => true,
+      // Signet shares testnet's Base58 and Bech32 prefixes, so its addresses parse as testnet's
+      (Network::Testnet, Network::Signet) => true,
+      // Regtest shares testnet's Base58 prefixes, yet has its own Bech32 prefix
+      (Network::Testnet, Network::Regtest) => {
+        !matches!(address.payload, Payload::WitnessProgram { .. })
+      }
+      _ => false,
+    };
+    if !valid {
+      Err(CoinError::InvalidAddress)?;
+    }
+    Ok(address)
+  }
+
+  async fn median_time_past(&self, number: usize) -> Result<u64, CoinError> {
+    let block_hash =
+      self.rpc.get_block_hash(number).await.map_err(|_| CoinError::ConnectionError)?;
+    self.rpc.get_median_time_past(&block_hash).await.map_err(|_| CoinError::ConnectionError)
+  }
+
+  /// Check the block previously scanned at the specified number is still part of the chain,
+  /// returning how many blocks were reorganized out from it back. Errors if this exceeds
+  /// MAX_REORG_DEPTH, in which case scanning must not proceed.
+  pub async fn check_reorg(&self, number: usize, id: [u8; 32]) -> Result<usize, CoinError> {
+    let latest = self.get_latest_block_number().await?;
+    let mut hash = BlockHash::from_inner(id);
+    // Walk back from the scanned block until reaching a block which is still in the chain
+    // The genesis block is never reorganized out
+    let mut height = number;
+    while height != 0 {
+      if height <= latest {
+        let active =
+          self.rpc.get_block_hash(height).await.map_err(|_| CoinError::ConnectionError)?;
+        if active == hash {
+          break;
+        }
+      }
+
+      let block = self.rpc.get_block(&hash).await.map_err(|_| CoinError::ConnectionError)?;
+      hash = block.header.prev_blockhash;
+      height -= 1;
+      if (number - height) > Self::MAX_REORG_DEPTH {
+        Err(CoinError::ReorgTooDeep { depth: number - height })?;
+      }
+    }
+    Ok(number - height)
+  }
+
+  /// Scan a block for outputs to any of the specified keys in a single pass, such as for both
+  /// the current and the prior key while rotating keys. Returns each output with the key it's to.
+  pub async fn get_outputs_multi(
+    &self,
+    block: &Block,
+    keys: &[ProjectivePoint],
+  ) -> Result<Vec<(ProjectivePoint, Output)>, CoinError> {
+    let mut scripts = HashMap::new();
+    for key in keys {
+      // Keys must have been tweaked to have an even Y coordinate, else the addresses scanned for
+      // would be for the key's negation, silently missing every output
+      if key.to_encoded_point(true).tag() != Tag::CompressedEvenY {
+        Err(CoinError::UntweakedKey)?;
+      }
+
+      let mut entry = |pair: (ProjectivePoint, _), kind| {
+        scripts.insert(self.address(pair.0).script_pubkey().to_bytes(), (*key, pair.1, kind));
+      };
+      entry((*key, Scalar::ZERO), OutputType::External);
+      entry(branch(*key), OutputType::Branch);
+      for index in 0 .. self.change_addresses {
+        entry(change(*key, index), OutputType::Change);
+      }
+    }
+
+    let mut outputs = Vec::new();
+    // Skip the coinbase transaction which is burdened by maturity
+    for tx in &block.txdata[1 ..] {
+      for (vout, output) in tx.output.iter().enumerate() {
+        if let Some((key, offset, kind)) = scripts.get(&output.script_pubkey.to_bytes()) {
+          // Zero-value outputs can't fund anything, so don't clutter the tracked outputs with them
+          if output.value == 0 {
+            debug!("skipping zero-value output {}:{}", tx.txid(), vout);
+            continue;
+          }
+          if output.value > MAX_MONEY {
+            warn!("skipping output {}:{} with an impossible value", tx.txid(), vout);
+            continue;
+          }
+
+          outputs.push((
+            *key,
+            Output(
+              SpendableOutput {
+                offset: *offset,
+                output: output.clone(),
+                outpoint: OutPoint { txid: tx.txid(), vout: u32::try_from(vout).unwrap() },
+              },
+              *kind,
+            ),
+          ));
+        }
+      }
+    }
+
+    Ok(outputs)
+  }
+
+  /// Fetch the transaction which created an output, as needed to prove its inclusion.
+  // TODO: Cache these, as proofs are likely to be generated for several outputs per transaction
+  pub async fn transaction_for(&self, output: &Output) -> Result<Transaction, CoinError> {
+    self.rpc.get_transaction(&output.0.outpoint.txid).await.map_err(|_| CoinError::ConnectionError)
+  }
+
+  /// Produce a proof of the inclusion of the transaction which created an output, verifiable
+  /// against the merkle root of the returned block header.
+  pub async fn inclusion_proof(
+    &self,
+    output: &Output,
+  ) -> Result<(MerkleProof, BlockHeader), CoinError> {
+    let txid = output.0.outpoint.txid;
+    let hash = self
+      .rpc
+      .get_transaction_block(&txid)
+      .await
+      .map_err(|_| CoinError::ConnectionError)?
+      .ok_or(CoinError::NotIncluded)?;
+    let block = self.rpc.get_block(&hash).await.map_err(|_| CoinError::ConnectionError)?;
+    // Don't trust the node to have returned the block which actually includes this transaction
+    if !block.txdata.iter().any(|tx| tx.txid() == txid) {
+      Err(CoinError::ConnectionError)?;
+    }
+
+    let MerkleBlock { header, txn } =
+      MerkleBlock::from_block_with_predicate(&block, |id| *id == txid);
+    Ok((txn, header))
+  }
+
+  /// Rebuild a transaction paying a higher fee, so it can replace the original in the mempool.
+  /// The minimum relay fee is specified in satoshis per virtual byte. Errors with FeeTooLow if
+  /// the replacement wouldn't be relayed under BIP-125.
+  pub fn bump_fee(
+    &self,
+    tx: &SignableTransaction,
+    fee: Fee,
+    min_relay_fee: u64,
+  ) -> Result<SignableTransaction, CoinError> {
+    Ok(SignableTransaction {
+      keys: tx.keys.clone(),
+      transcript: tx.transcript.clone(),
+      actual: tx.actual.bump_fee(fee.0, min_relay_fee).map_err(transaction_error)?,
+    })
   }
 
   #[cfg(test)]
@@ -133,12 +446,21 @@ impl Coin for Bitcoin {
   type Address = Address;
 
   const ID: &'static [u8] = b"Bitcoin";
+  const DECIMALS: u8 = 8;
   const CONFIRMATIONS: usize = 3;
 
   // TODO: Get hard numbers and tune
   const MAX_INPUTS: usize = 128;
   const MAX_OUTPUTS: usize = 16;
 
+  fn max_inputs(&self) -> usize {
+    self.max_inputs
+  }
+
+  fn max_outputs(&self) -> usize {
+    self.max_outputs
+  }
+
   fn tweak_keys(&self, key: &mut ThresholdKeys<Self::Curve>) {
     let (_, offset) = make_even(key.group_key());
     *key = key.offset(Scalar::from(offset));
@@ -146,20 +468,51 @@ impl Coin for Bitcoin {
 
   fn address(&self, key: ProjectivePoint) -> Self::Address {
     debug_assert!(key.to_encoded_point(true).tag() == Tag::CompressedEvenY, "YKey is odd");
-    Address::p2tr_tweaked(
-      TweakedPublicKey::dangerous_assume_tweaked(x_only(&key)),
-      Network::Regtest,
-    )
+    Address::p2tr_tweaked(TweakedPublicKey::dangerous_assume_tweaked(x_only(&key)), self.network)
   }
 
   fn branch_address(&self, key: ProjectivePoint) -> Self::Address {
     self.address(branch(key).0)
   }
 
+  fn scan_from(&self) -> usize {
+    self.scan_from
+  }
+
   async fn get_latest_block_number(&self) -> Result<usize, CoinError> {
     Ok(self.rpc.get_latest_block_number().await.map_err(|_| CoinError::ConnectionError)?)
   }
 
+  async fn get_confirmed_block_number(&self) -> Result<Option<usize>, CoinError> {
+    let latest = self.get_latest_block_number().await?;
+    match self.confirmation_policy {
+      ConfirmationPolicy::Blocks(confirmations) => Ok(latest.checked_sub(confirmations - 1)),
+      ConfirmationPolicy::Time(duration) => {
+        let tip = self.median_time_past(latest).await?;
+        let threshold = match tip.checked_sub(duration.as_secs()) {
+          Some(threshold) => threshold,
+          None => return Ok(None),
+        };
+        if self.median_time_past(0).await? > threshold {
+          return Ok(None);
+        }
+
+        // Median time past is monotonic, so the blocks far enough in the past are a prefix of the
+        // chain, whose end is binary searched for instead of walking back from the tip
+        let (mut confirmed, mut unconfirmed) = (0, latest + 1);
+        while (unconfirmed - confirmed) > 1 {
+          let middle = confirmed + ((unconfirmed - confirmed) / 2);
+          if self.median_time_past(middle).await? <= threshold {
+            confirmed = middle;
+          } else {
+            unconfirmed = middle;
+          }
+        }
+        Ok(Some(confirmed))
+      }
+    }
+  }
+
   async fn get_block(&self, number: usize) -> Result<Self::Block, CoinError> {
     let block_hash =
       self.rpc.get_block_hash(number).await.map_err(|_| CoinError::ConnectionError)?;
@@ -171,59 +524,38 @@ impl Coin for Bitcoin {
     block: &Self::Block,
     key: ProjectivePoint,
   ) -> Result<Vec<Self::Output>, CoinError> {
-    let external = (key, Scalar::ZERO);
-    let branch = branch(key);
-    let change = change(key);
-
-    let entry =
-      |pair: (_, _), kind| (self.address(pair.0).script_pubkey().to_bytes(), (pair.1, kind));
-    let scripts = HashMap::from([
-      entry(external, OutputType::External),
-      entry(branch, OutputType::Branch),
-      entry(change, OutputType::Change),
-    ]);
-
-    let mut outputs = Vec::new();
-    // Skip the coinbase transaction which is burdened by maturity
-    for tx in &block.txdata[1 ..] {
-      for (vout, output) in tx.output.iter().enumerate() {
-        if let Some(info) = scripts.get(&output.script_pubkey.to_bytes()) {
-          outputs.push(Output(
-            SpendableOutput {
-              offset: info.0,
-              output: output.clone(),
-              outpoint: OutPoint { txid: tx.txid(), vout: u32::try_from(vout).unwrap() },
-            },
-            info.1,
-          ));
-        }
-      }
-    }
-
-    Ok(outputs)
+    Ok(self.get_outputs_multi(block, &[key]).await?.drain(..).map(|(_, output)| output).collect())
   }
 
   async fn prepare_send(
     &self,
     keys: ThresholdKeys<Secp256k1>,
-    transcript: RecommendedTranscript,
+    mut transcript: RecommendedTranscript,
     _: usize,
     mut inputs: Vec<Output>,
-    payments: &[(Address, u64)],
+    payments: &[(Address, Amount)],
     change_key: Option<ProjectivePoint>,
+    change_index: usize,
     fee: Fee,
   ) -> Result<Self::SignableTransaction, CoinError> {
-    Ok(SignableTransaction {
-      keys,
-      transcript,
-      actual: BSignableTransaction::new(
-        inputs.drain(..).map(|input| input.0).collect(),
-        payments,
-        change_key.map(|change_key| self.address(change(change_key).0)),
-        fee.0,
-      )
-      .ok_or(CoinError::NotEnoughFunds)?,
-    })
+    if inputs.len() > self.max_inputs() {
+      Err(CoinError::TooManyInputs)?;
+    }
+    if payments.len() > self.max_outputs() {
+      Err(CoinError::TooManyOutputs)?;
+    }
+
+    bind_transcript::<Self>(&mut transcript);
+    let actual = BSignableTransaction::new(
+      inputs.drain(..).map(|input| input.0).collect(),
+      &payments.iter().map(|(address, amount)| (address.clone(), amount.0)).collect::<Vec<_>>(),
+      change_key.map(|key| self.address(change(key, change_index % self.change_addresses).0)),
+      fee.0,
+    )
+    .map_err(transaction_error)?;
+    debug_assert!(actual.fee() >= (fee.0 * u64::try_from(actual.signed_weight()).unwrap()));
+
+    Ok(SignableTransaction { keys, transcript, actual })
   }
 
   async fn attempt_send(
@@ -239,7 +571,12 @@ impl Coin for Bitcoin {
   }
 
   async fn publish_transaction(&self, tx: &Self::Transaction) -> Result<Vec<u8>, CoinError> {
-    Ok(self.rpc.send_raw_transaction(tx).await.unwrap().to_vec())
+    match self.rpc.send_raw_transaction(tx).await {
+      Ok(txid) => Ok(txid.to_vec()),
+      // The node responded, refusing the transaction, so retrying won't help
+      Err(RpcError::RequestError(..)) => Err(CoinError::TransactionRejected),
+      Err(_) => Err(CoinError::ConnectionError),
+    }
   }
 
   #[cfg(test)]
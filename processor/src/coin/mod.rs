@@ -3,7 +3,7 @@ use std::io;
 use async_trait::async_trait;
 use thiserror::Error;
 
-use transcript::RecommendedTranscript;
+use transcript::{Transcript, RecommendedTranscript};
 use frost::{
   curve::{Ciphersuite, Curve},
   ThresholdKeys,
@@ -22,6 +22,88 @@ pub enum CoinError {
   ConnectionError,
   #[error("not enough funds")]
   NotEnoughFunds,
+  #[error("amount overflowed")]
+  AmountOverflow,
+  #[error("fee wasn't high enough to replace the transaction")]
+  FeeTooLow,
+  #[error("transaction had too many inputs")]
+  TooManyInputs,
+  #[error("transaction had too many outputs")]
+  TooManyOutputs,
+  #[error("invalid address")]
+  InvalidAddress,
+  #[error("key wasn't tweaked for this coin")]
+  UntweakedKey,
+  #[error("invalid amount")]
+  InvalidAmount,
+  #[error("transaction was rejected")]
+  TransactionRejected,
+  #[error("transaction wasn't included in a block")]
+  NotIncluded,
+  #[error("reorganization of at least {depth} blocks exceeded the maximum depth")]
+  ReorgTooDeep { depth: usize },
+}
+
+/// An amount of a coin, in its atomic units, with checked arithmetic.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default, Debug)]
+pub struct Amount(pub u64);
+
+impl Amount {
+  pub fn checked_add(self, other: Amount) -> Result<Amount, CoinError> {
+    self.0.checked_add(other.0).map(Amount).ok_or(CoinError::AmountOverflow)
+  }
+
+  // Underflowing means the amount being subtracted isn't available
+  pub fn checked_sub(self, other: Amount) -> Result<Amount, CoinError> {
+    self.0.checked_sub(other.0).map(Amount).ok_or(CoinError::NotEnoughFunds)
+  }
+
+  pub fn checked_mul(self, other: u64) -> Result<Amount, CoinError> {
+    self.0.checked_mul(other).map(Amount).ok_or(CoinError::AmountOverflow)
+  }
+
+  pub fn saturating_add(self, other: Amount) -> Amount {
+    Amount(self.0.saturating_add(other.0))
+  }
+
+  pub fn saturating_sub(self, other: Amount) -> Amount {
+    Amount(self.0.saturating_sub(other.0))
+  }
+
+  pub fn sum<I: IntoIterator<Item = Amount>>(amounts: I) -> Result<Amount, CoinError> {
+    amounts.into_iter().try_fold(Amount(0), Amount::checked_add)
+  }
+
+  /// Format this amount as a decimal, with the specified amount of decimal places.
+  pub fn format(self, decimals: u8) -> String {
+    if decimals == 0 {
+      return self.0.to_string();
+    }
+    let unit = 10u64.pow(decimals.into());
+    format!("{}.{:0width$}", self.0 / unit, self.0 % unit, width = usize::from(decimals))
+  }
+
+  /// Parse a decimal amount, with at most the specified amount of decimal places.
+  pub fn parse(amount: &str, decimals: u8) -> Result<Amount, CoinError> {
+    let (whole, fraction) = amount.split_once('.').unwrap_or((amount, ""));
+    let digits = |value: &str| value.bytes().all(|byte| byte.is_ascii_digit());
+    if whole.is_empty() ||
+      (!digits(whole)) ||
+      (!digits(fraction)) ||
+      (fraction.len() > usize::from(decimals)) ||
+      amount.ends_with('.')
+    {
+      Err(CoinError::InvalidAmount)?;
+    }
+
+    let parse = |value: &str| value.parse::<u64>().map_err(|_| CoinError::AmountOverflow);
+    let whole = Amount(parse(whole)?).checked_mul(10u64.pow(decimals.into()))?;
+    if fraction.is_empty() {
+      return Ok(whole);
+    }
+    let padding = u32::from(decimals) - u32::try_from(fraction.len()).unwrap();
+    whole.checked_add(Amount(parse(fraction)? * 10u64.pow(padding)))
+  }
 }
 
 pub trait Block: Sized + Clone {
@@ -36,8 +118,9 @@ pub enum OutputType {
   Change,
 }
 
+// Serialized alongside outputs so their classification survives being persisted and reloaded
 impl OutputType {
-  fn write<W: io::Write>(&self, writer: &mut W) -> io::Result<()> {
+  pub fn write<W: io::Write>(&self, writer: &mut W) -> io::Result<()> {
     writer.write_all(&[match self {
       OutputType::External => 0,
       OutputType::Branch => 1,
@@ -45,14 +128,14 @@ impl OutputType {
     }])
   }
 
-  fn read<R: io::Read>(reader: &mut R) -> io::Result<Self> {
+  pub fn read<R: io::Read>(reader: &mut R) -> io::Result<Self> {
     let mut byte = [0; 1];
     reader.read_exact(&mut byte)?;
     Ok(match byte[0] {
       0 => OutputType::External,
       1 => OutputType::Branch,
       2 => OutputType::Change,
-      _ => Err(io::Error::new(io::ErrorKind::Other, "invalid OutputType"))?,
+      _ => Err(io::Error::new(io::ErrorKind::InvalidData, "invalid OutputType"))?,
     })
   }
 }
@@ -63,14 +146,14 @@ pub trait Output: Sized + Clone {
   fn kind(&self) -> OutputType;
 
   fn id(&self) -> Self::Id;
-  fn amount(&self) -> u64;
+  fn amount(&self) -> Amount;
 
   fn serialize(&self) -> Vec<u8>;
   fn read<R: std::io::Read>(reader: &mut R) -> std::io::Result<Self>;
 }
 
 #[async_trait]
-pub trait Coin {
+pub trait Coin: Sync {
   type Curve: Curve;
 
   type Fee: Copy;
@@ -78,16 +161,36 @@ pub trait Coin {
   type Block: Block;
 
   type Output: Output;
-  type SignableTransaction;
+  type SignableTransaction: Send;
   type TransactionMachine: PreprocessMachine<Signature = Self::Transaction>;
 
   type Address: Send;
 
   const ID: &'static [u8];
+  /// Amount of decimal places amounts are displayed with.
+  const DECIMALS: u8;
   const CONFIRMATIONS: usize;
   const MAX_INPUTS: usize;
   const MAX_OUTPUTS: usize; // TODO: Decide if this includes change or not
 
+  /// Maximum amount of inputs a transaction may have, as currently configured.
+  fn max_inputs(&self) -> usize {
+    Self::MAX_INPUTS
+  }
+  /// Maximum amount of outputs a transaction may have, as currently configured.
+  fn max_outputs(&self) -> usize {
+    Self::MAX_OUTPUTS
+  }
+
+  /// Format an amount, in atomic units, for display.
+  fn format_amount(amount: u64) -> String {
+    Amount(amount).format(Self::DECIMALS)
+  }
+  /// Parse a displayed amount into atomic units.
+  fn parse_amount(amount: &str) -> Result<u64, CoinError> {
+    Amount::parse(amount, Self::DECIMALS).map(|amount| amount.0)
+  }
+
   fn tweak_keys(&self, key: &mut ThresholdKeys<Self::Curve>);
 
   /// Address for the given group key to receive external coins to.
@@ -96,7 +199,18 @@ pub trait Coin {
   /// Address for the given group key to use for scheduled branches.
   fn branch_address(&self, key: <Self::Curve as Ciphersuite>::G) -> Self::Address;
 
+  /// Number of the first block to scan, such as the block the keys were generated at. Blocks
+  /// prior to it are never scanned.
+  fn scan_from(&self) -> usize {
+    0
+  }
+
   async fn get_latest_block_number(&self) -> Result<usize, CoinError>;
+  /// Number of the latest block considered confirmed, if any block is. By default, this is the
+  /// block with CONFIRMATIONS - 1 blocks built on top of it.
+  async fn get_confirmed_block_number(&self) -> Result<Option<usize>, CoinError> {
+    Ok(self.get_latest_block_number().await?.checked_sub(Self::CONFIRMATIONS - 1))
+  }
   async fn get_block(&self, number: usize) -> Result<Self::Block, CoinError>;
   async fn get_outputs(
     &self,
@@ -111,8 +225,9 @@ pub trait Coin {
     transcript: RecommendedTranscript,
     block_number: usize,
     inputs: Vec<Self::Output>,
-    payments: &[(Self::Address, u64)],
+    payments: &[(Self::Address, Amount)],
     change: Option<<Self::Curve as Ciphersuite>::G>,
+    change_index: usize,
     fee: Self::Fee,
   ) -> Result<Self::SignableTransaction, CoinError>;
 
@@ -132,3 +247,44 @@ pub trait Coin {
   #[cfg(test)]
   async fn test_send(&self, key: Self::Address);
 }
+
+// Version of the domain separation applied to transaction transcripts
+const TRANSCRIPT_VERSION: u8 = 0;
+
+/// Bind a transaction's transcript to the coin it's for, so the same transcript used with multiple
+/// coins never produces correlated values.
+pub(crate) fn bind_transcript<C: Coin>(transcript: &mut RecommendedTranscript) {
+  transcript.domain_separate(b"Serai Processor Coin Transaction");
+  transcript.append_message(b"coin", C::ID);
+  transcript.append_message(b"version", [TRANSCRIPT_VERSION]);
+}
+
+/// Signer for transactions, performing the secret operations of signing, the FROST preprocess and
+/// share steps. This enables key shares to be held outside of this process, such as in an HSM or
+/// by a remote signer, while transactions are still constructed locally.
+#[async_trait]
+pub trait ThresholdSigner<C: Coin> {
+  type Machine: PreprocessMachine<Signature = C::Transaction>;
+
+  async fn attempt_send(
+    &self,
+    coin: &C,
+    transaction: C::SignableTransaction,
+  ) -> Result<Self::Machine, CoinError>;
+}
+
+/// Signer using the key share held in memory, as present in the transaction being signed.
+pub struct LocalSigner;
+
+#[async_trait]
+impl<C: Coin> ThresholdSigner<C> for LocalSigner {
+  type Machine = C::TransactionMachine;
+
+  async fn attempt_send(
+    &self,
+    coin: &C,
+    transaction: C::SignableTransaction,
+  ) -> Result<C::TransactionMachine, CoinError> {
+    coin.attempt_send(transaction).await
+  }
+}
@@ -21,7 +21,10 @@ use monero_serai::{
 
 use crate::{
   additional_key,
-  coin::{CoinError, Block as BlockTrait, OutputType, Output as OutputTrait, Coin},
+  coin::{
+    CoinError, Amount, Block as BlockTrait, OutputType, Output as OutputTrait, Coin,
+    bind_transcript,
+  },
 };
 
 #[derive(Clone, Debug)]
@@ -64,8 +67,8 @@ impl OutputTrait for Output {
     self.0.output.data.key.compress().to_bytes()
   }
 
-  fn amount(&self) -> u64 {
-    self.0.commitment().amount
+  fn amount(&self) -> Amount {
+    Amount(self.0.commitment().amount)
   }
 
   fn serialize(&self) -> Vec<u8> {
@@ -152,6 +155,7 @@ impl Coin for Monero {
   type Address = MoneroAddress;
 
   const ID: &'static [u8] = b"Monero";
+  const DECIMALS: u8 = 12;
   const CONFIRMATIONS: usize = 10;
   // Testnet TX bb4d188a4c571f2f0de70dca9d475abc19078c10ffa8def26dd4f63ce1bcfd79 uses 146 inputs
   // while using less than 100kb of space, albeit with just 2 outputs (though outputs share a BP)
@@ -221,13 +225,22 @@ impl Coin for Monero {
   async fn prepare_send(
     &self,
     keys: ThresholdKeys<Ed25519>,
-    transcript: RecommendedTranscript,
+    mut transcript: RecommendedTranscript,
     block_number: usize,
     mut inputs: Vec<Output>,
-    payments: &[(MoneroAddress, u64)],
+    payments: &[(MoneroAddress, Amount)],
     change: Option<dfg::EdwardsPoint>,
+    _: usize,
     fee: Fee,
   ) -> Result<SignableTransaction, CoinError> {
+    if inputs.len() > self.max_inputs() {
+      Err(CoinError::TooManyInputs)?;
+    }
+    if payments.len() > self.max_outputs() {
+      Err(CoinError::TooManyOutputs)?;
+    }
+
+    bind_transcript::<Self>(&mut transcript);
     Ok(SignableTransaction {
       keys,
       transcript,
@@ -235,7 +248,7 @@ impl Coin for Monero {
       actual: MSignableTransaction::new(
         self.rpc.get_protocol().await.unwrap(), // TODO: Make this deterministic
         inputs.drain(..).map(|input| input.0).collect(),
-        payments.to_vec(),
+        payments.iter().map(|(address, amount)| (*address, amount.0)).collect(),
         change.map(|change| self.address_internal(change, CHANGE_SUBADDRESS)),
         vec![],
         fee,
@@ -0,0 +1,30 @@
+use crate::coin::{CoinError, Amount, Coin, Bitcoin};
+
+#[test]
+fn amount_overflow() {
+  assert_eq!(Amount::sum([Amount(1), Amount(2)]).unwrap(), Amount(3));
+  assert!(matches!(Amount::sum([Amount(u64::MAX), Amount(1)]), Err(CoinError::AmountOverflow)));
+  assert!(matches!(Amount(u64::MAX).checked_mul(2), Err(CoinError::AmountOverflow)));
+  assert_eq!(Amount(u64::MAX).saturating_add(Amount(1)), Amount(u64::MAX));
+
+  // A fee exceeding the input should error, not wrap
+  assert!(matches!(Amount(1000).checked_sub(Amount(1001)), Err(CoinError::NotEnoughFunds)));
+  assert_eq!(Amount(1000).saturating_sub(Amount(1001)), Amount(0));
+}
+
+#[test]
+fn amount_format() {
+  assert_eq!(Bitcoin::format_amount(123456789), "1.23456789");
+  assert_eq!(Bitcoin::parse_amount("1.23456789").unwrap(), 123456789);
+  assert_eq!(Bitcoin::parse_amount(&Bitcoin::format_amount(1)).unwrap(), 1);
+  assert_eq!(Bitcoin::parse_amount("1.5").unwrap(), 150000000);
+  assert_eq!(Bitcoin::parse_amount("21000000").unwrap(), 21000000 * 100000000);
+  assert_eq!(Amount(5).format(0), "5");
+
+  // More precision than the coin has is rejected, instead of being rounded
+  assert!(matches!(Bitcoin::parse_amount("1.234567891"), Err(CoinError::InvalidAmount)));
+  for malformed in ["", ".5", "1.", "-1", "1.-5", "1.2.3", "1e8", " 1"] {
+    assert!(matches!(Bitcoin::parse_amount(malformed), Err(CoinError::InvalidAmount)));
+  }
+  assert!(matches!(Bitcoin::parse_amount("184467440738"), Err(CoinError::AmountOverflow)));
+}
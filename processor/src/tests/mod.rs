@@ -1,5 +1,7 @@
+mod amount;
+
 mod send;
-pub(crate) use send::test_send;
+pub(crate) use send::{test_send, test_batch_send};
 
 mod bitcoin;
 mod monero;
@@ -9,7 +9,7 @@ use rand_core::OsRng;
 
 use crate::{
   NetworkError, Network,
-  coin::Coin,
+  coin::{Amount, Coin},
   wallet::{WalletKeys, MemCoinDb, Wallet},
 };
 
@@ -58,14 +58,15 @@ impl Network for LocalNetwork {
   }
 }
 
-pub async fn test_send<C: Coin + Clone>(coin: C, fee: C::Fee) {
+// Create a wallet, and a network to sign with, for each signer of a fresh set of keys
+async fn wallets<C: Coin + Clone>(coin: &C) -> (Vec<LocalNetwork>, Vec<Wallet<MemCoinDb, C>>) {
   // Mine blocks so there's a confirmed block
   coin.mine_block().await;
   let latest = coin.get_latest_block_number().await.unwrap();
 
   let mut keys = frost::tests::key_gen::<_, C::Curve>(&mut OsRng);
   let threshold = keys[&1].params().t();
-  let mut networks = LocalNetwork::new(threshold);
+  let networks = LocalNetwork::new(threshold);
 
   let mut wallets = vec![];
   for i in 1 ..= threshold {
@@ -85,6 +86,12 @@ pub async fn test_send<C: Coin + Clone>(coin: C, fee: C::Fee) {
     wallet.poll().await.unwrap();
   }
 
+  (networks, wallets)
+}
+
+pub async fn test_send<C: Coin + Clone>(coin: C, fee: C::Fee) {
+  let (mut networks, mut wallets) = wallets(&coin).await;
+
   coin.test_send(wallets[0].address()).await;
 
   let mut futures = vec![];
@@ -94,7 +101,7 @@ pub async fn test_send<C: Coin + Clone>(coin: C, fee: C::Fee) {
     let latest = coin.get_latest_block_number().await.unwrap();
     wallet.acknowledge_block(1, latest - (C::CONFIRMATIONS - 1));
     let signable = wallet
-      .prepare_sends(1, vec![(wallet.address(), 100000000)], fee)
+      .prepare_sends(1, vec![(wallet.address(), Amount(100000000))], fee)
       .await
       .unwrap()
       .1
@@ -104,3 +111,36 @@ pub async fn test_send<C: Coin + Clone>(coin: C, fee: C::Fee) {
 
   println!("{:?}", hex::encode(futures::future::join_all(futures).await.swap_remove(0).unwrap()));
 }
+
+pub async fn test_batch_send<C: Coin + Clone>(coin: C, fee: C::Fee) {
+  let (mut networks, mut wallets) = wallets(&coin).await;
+
+  // Fund the wallet with an output for each transaction
+  for _ in 0 .. 3 {
+    coin.test_send(wallets[0].address()).await;
+  }
+
+  let mut futures = vec![];
+  for (network, wallet) in networks.iter_mut().zip(wallets.iter_mut()) {
+    wallet.poll().await.unwrap();
+
+    let latest = coin.get_latest_block_number().await.unwrap();
+    wallet.acknowledge_block(1, latest - (C::CONFIRMATIONS - 1));
+    // Prepare three independent transactions, each spending one of the outputs
+    let mut signables = vec![];
+    for _ in 0 .. 3 {
+      let payments = vec![(wallet.address(), Amount(100000000))];
+      signables.extend(wallet.prepare_sends(1, payments, fee).await.unwrap().1);
+    }
+    assert_eq!(signables.len(), 3);
+    futures.push(wallet.attempt_send_batch(network, signables));
+  }
+
+  // Every transaction was signed and published
+  let ids = futures::future::join_all(futures).await.swap_remove(0).unwrap();
+  assert_eq!(ids.len(), 3);
+  assert!((ids[0] != ids[1]) && (ids[0] != ids[2]) && (ids[1] != ids[2]));
+
+  // With only a single exchange of preprocesses, and a single exchange of shares
+  assert!(networks.iter().all(|network| network.round == 2));
+}
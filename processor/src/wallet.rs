@@ -12,7 +12,7 @@ use frost::{
 };
 
 use crate::{
-  coin::{CoinError, Output, Coin},
+  coin::{CoinError, Amount, Output, Coin, ThresholdSigner, LocalSigner},
   SignError, Network,
 };
 
@@ -105,33 +105,38 @@ impl CoinDb for MemCoinDb {
   }
 }
 
-fn select_inputs<C: Coin>(inputs: &mut Vec<C::Output>) -> (Vec<C::Output>, u64) {
+fn select_inputs<C: Coin>(
+  inputs: &mut Vec<C::Output>,
+  max_inputs: usize,
+) -> (Vec<C::Output>, Amount) {
   // Sort to ensure determinism. Inefficient, yet produces the most legible code to be optimized
   // later
   inputs.sort_by_key(|a| a.amount());
 
   // Select the maximum amount of outputs possible
-  let res = inputs.split_off(inputs.len() - C::MAX_INPUTS.min(inputs.len()));
+  let res = inputs.split_off(inputs.len() - max_inputs.min(inputs.len()));
   // Calculate their sum value, minus the fee needed to spend them
-  let sum = res.iter().map(|input| input.amount()).sum();
+  // This saturates as it's solely used to select payments, and prepare_send will error on overflow
+  let sum = res.iter().fold(Amount(0), |sum, input| sum.saturating_add(input.amount()));
   // sum -= C::MAX_FEE; // TODO
   (res, sum)
 }
 
 fn select_outputs<C: Coin>(
-  payments: &mut Vec<(C::Address, u64)>,
-  value: &mut u64,
-) -> Vec<(C::Address, u64)> {
+  payments: &mut Vec<(C::Address, Amount)>,
+  value: &mut Amount,
+  max_outputs: usize,
+) -> Vec<(C::Address, Amount)> {
   // Prioritize large payments which will most efficiently use large inputs
   payments.sort_by(|a, b| a.1.cmp(&b.1));
 
   // Grab the payments this will successfully fund
   let mut outputs = vec![];
   let mut p = payments.len();
-  while p != 0 {
+  while (p != 0) && (outputs.len() < max_outputs) {
     p -= 1;
     if *value >= payments[p].1 {
-      *value -= payments[p].1;
+      *value = value.saturating_sub(payments[p].1);
       // Swap remove will either pop the tail or insert an element that wouldn't fit, making it
       // always safe to move past
       outputs.push(payments.swap_remove(p));
@@ -146,12 +151,12 @@ fn select_outputs<C: Coin>(
 fn refine_inputs<C: Coin>(
   selected: &mut Vec<C::Output>,
   inputs: &mut Vec<C::Output>,
-  mut remaining: u64,
+  mut remaining: Amount,
 ) {
   // Drop unused inputs
   let mut s = 0;
   while remaining > selected[s].amount() {
-    remaining -= selected[s].amount();
+    remaining = remaining.saturating_sub(selected[s].amount());
     s += 1;
   }
   // Add them back to the inputs pool
@@ -168,9 +173,9 @@ fn refine_inputs<C: Coin>(
       }
 
       // If we can successfully replace this input, do so
-      let diff = selected[s].amount() - input.amount();
+      let diff = selected[s].amount().saturating_sub(input.amount());
       if remaining > diff {
-        remaining -= diff;
+        remaining = remaining.saturating_sub(diff);
 
         let old = selected[s].clone();
         selected[s] = input.clone();
@@ -182,16 +187,17 @@ fn refine_inputs<C: Coin>(
 
 #[allow(clippy::type_complexity)]
 fn select_inputs_outputs<C: Coin>(
+  coin: &C,
   inputs: &mut Vec<C::Output>,
-  outputs: &mut Vec<(C::Address, u64)>,
-) -> (Vec<C::Output>, Vec<(C::Address, u64)>) {
+  outputs: &mut Vec<(C::Address, Amount)>,
+) -> (Vec<C::Output>, Vec<(C::Address, Amount)>) {
   if inputs.is_empty() {
     return (vec![], vec![]);
   }
 
-  let (mut selected, mut value) = select_inputs::<C>(inputs);
+  let (mut selected, mut value) = select_inputs::<C>(inputs, coin.max_inputs());
 
-  let outputs = select_outputs::<C>(outputs, &mut value);
+  let outputs = select_outputs::<C>(outputs, &mut value, coin.max_outputs());
   if outputs.is_empty() {
     inputs.extend(selected);
     return (vec![], vec![]);
@@ -205,7 +211,8 @@ fn select_inputs_outputs<C: Coin>(
 pub struct Wallet<D: CoinDb, C: Coin> {
   db: D,
   coin: C,
-  keys: Vec<(ThresholdKeys<C::Curve>, Vec<C::Output>)>,
+  // Each set of keys, their outputs, and how many transactions they've sent
+  keys: Vec<(ThresholdKeys<C::Curve>, Vec<C::Output>, usize)>,
   pending: Vec<(usize, ThresholdKeys<C::Curve>)>,
 }
 
@@ -236,13 +243,13 @@ impl<D: CoinDb, C: Coin> Wallet<D, C> {
   }
 
   pub async fn poll(&mut self) -> Result<(), CoinError> {
-    if self.coin.get_latest_block_number().await? < (C::CONFIRMATIONS - 1) {
-      return Ok(());
-    }
-    let confirmed_block = self.coin.get_latest_block_number().await? - (C::CONFIRMATIONS - 1);
+    let confirmed_block = match self.coin.get_confirmed_block_number().await? {
+      Some(confirmed_block) => confirmed_block,
+      None => return Ok(()),
+    };
 
     // Will never scan the genesis block, which shouldn't be an issue
-    for b in (self.scanned_block() + 1) ..= confirmed_block {
+    for b in (self.scanned_block() + 1).max(self.coin.scan_from()) ..= confirmed_block {
       // If any keys activated at this block, shift them over
       {
         let mut k = 0;
@@ -251,7 +258,7 @@ impl<D: CoinDb, C: Coin> Wallet<D, C> {
           //if b < self.pending[k].0 {
           //} else if b == self.pending[k].0 {
           if b <= self.pending[k].0 {
-            self.keys.push((self.pending.swap_remove(k).1, vec![]));
+            self.keys.push((self.pending.swap_remove(k).1, vec![], 0));
           } else {
             k += 1;
           }
@@ -259,7 +266,7 @@ impl<D: CoinDb, C: Coin> Wallet<D, C> {
       }
 
       let block = self.coin.get_block(b).await?;
-      for (keys, outputs) in self.keys.iter_mut() {
+      for (keys, outputs, _) in self.keys.iter_mut() {
         outputs.extend(
           self
             .coin
@@ -284,25 +291,25 @@ impl<D: CoinDb, C: Coin> Wallet<D, C> {
   pub async fn prepare_sends(
     &mut self,
     canonical: usize,
-    mut payments: Vec<(C::Address, u64)>,
+    mut payments: Vec<(C::Address, Amount)>,
     fee: C::Fee,
-  ) -> Result<(Vec<(C::Address, u64)>, Vec<C::SignableTransaction>), CoinError> {
+  ) -> Result<(Vec<(C::Address, Amount)>, Vec<C::SignableTransaction>), CoinError> {
     if payments.is_empty() {
       return Ok((vec![], vec![]));
     }
 
     let acknowledged_block = self.acknowledged_block(canonical);
 
-    // TODO: Log schedule outputs when MAX_OUTPUTS is lower than payments.len()
+    // TODO: Log schedule outputs when max_outputs is lower than payments.len()
     // Payments is the first set of TXs in the schedule
     // As each payment re-appears, let mut payments = schedule[payment] where the only input is
     // the source payment
     // let (mut payments, schedule) = schedule(payments);
 
     let mut txs = vec![];
-    for (keys, outputs) in self.keys.iter_mut() {
+    for (keys, outputs, sent) in self.keys.iter_mut() {
       while !outputs.is_empty() {
-        let (inputs, outputs) = select_inputs_outputs::<C>(outputs, &mut payments);
+        let (inputs, outputs) = select_inputs_outputs::<C>(&self.coin, outputs, &mut payments);
         // If we can no longer process any payments, move to the next set of keys
         if outputs.is_empty() {
           debug_assert_eq!(inputs.len(), 0);
@@ -318,6 +325,9 @@ impl<D: CoinDb, C: Coin> Wallet<D, C> {
           u64::try_from(acknowledged_block).unwrap().to_le_bytes(),
         );
         transcript.append_message(b"index", u64::try_from(txs.len()).unwrap().to_le_bytes());
+        // The change address is rotated per transaction sent by these keys
+        // This is bound so any disagreement on it causes signing to fail
+        transcript.append_message(b"change_index", u64::try_from(*sent).unwrap().to_le_bytes());
 
         let tx = self
           .coin
@@ -328,9 +338,11 @@ impl<D: CoinDb, C: Coin> Wallet<D, C> {
             inputs,
             &outputs,
             Some(keys.group_key()),
+            *sent,
             fee,
           )
           .await?;
+        *sent += 1;
         // self.db.save_tx(tx) // TODO
         txs.push(tx);
       }
@@ -344,42 +356,106 @@ impl<D: CoinDb, C: Coin> Wallet<D, C> {
     network: &mut N,
     prepared: C::SignableTransaction,
   ) -> Result<Vec<u8>, SignError> {
-    let attempt = self.coin.attempt_send(prepared).await.map_err(SignError::CoinError)?;
-
-    let (attempt, commitments) = attempt.preprocess(&mut OsRng);
-    let commitments = network
-      .round(commitments.serialize())
-      .await
-      .map_err(SignError::NetworkError)?
-      .drain()
-      .map(|(validator, preprocess)| {
-        Ok((
+    self.attempt_send_with(network, &LocalSigner, prepared).await
+  }
+
+  /// Sign and publish a transaction, with the secret operations performed by the specified signer.
+  pub async fn attempt_send_with<N: Network, S: ThresholdSigner<C>>(
+    &mut self,
+    network: &mut N,
+    signer: &S,
+    prepared: C::SignableTransaction,
+  ) -> Result<Vec<u8>, SignError> {
+    Ok(self.attempt_send_batch_with(network, signer, vec![prepared]).await?.swap_remove(0))
+  }
+
+  pub async fn attempt_send_batch<N: Network>(
+    &mut self,
+    network: &mut N,
+    prepared: Vec<C::SignableTransaction>,
+  ) -> Result<Vec<Vec<u8>>, SignError> {
+    self.attempt_send_batch_with(network, &LocalSigner, prepared).await
+  }
+
+  /// Sign and publish several independent transactions, with the secret operations performed by
+  /// the specified signer. The FROST sessions are batched, so a single round-trip is used for all
+  /// of their preprocesses (and another for all of their shares), while each transaction is still
+  /// bound to its own transcript.
+  pub async fn attempt_send_batch_with<N: Network, S: ThresholdSigner<C>>(
+    &mut self,
+    network: &mut N,
+    signer: &S,
+    mut prepared: Vec<C::SignableTransaction>,
+  ) -> Result<Vec<Vec<u8>>, SignError> {
+    if prepared.is_empty() {
+      return Ok(vec![]);
+    }
+
+    // Each participant sends the preprocesses for every transaction, in order, as one message
+    let mut attempts = vec![];
+    let mut preprocesses = vec![];
+    for prepared in prepared.drain(..) {
+      let attempt =
+        signer.attempt_send(&self.coin, prepared).await.map_err(SignError::CoinError)?;
+      let (attempt, preprocess) = attempt.preprocess(&mut OsRng);
+      attempts.push(attempt);
+      preprocesses.extend(preprocess.serialize());
+    }
+
+    let mut commitments = attempts.iter().map(|_| HashMap::new()).collect::<Vec<_>>();
+    for (validator, preprocesses) in
+      network.round(preprocesses).await.map_err(SignError::NetworkError)?.drain()
+    {
+      let mut preprocesses = preprocesses.as_slice();
+      for (attempt, commitments) in attempts.iter().zip(commitments.iter_mut()) {
+        commitments.insert(
           validator,
           attempt
-            .read_preprocess::<&[u8]>(&mut preprocess.as_ref())
+            .read_preprocess::<&[u8]>(&mut preprocesses)
             .map_err(|_| SignError::FrostError(FrostError::InvalidPreprocess(validator)))?,
-        ))
-      })
-      .collect::<Result<HashMap<_, _>, _>>()?;
-
-    let (attempt, share) = attempt.sign(commitments, b"").map_err(SignError::FrostError)?;
-    let shares = network
-      .round(share.serialize())
-      .await
-      .map_err(SignError::NetworkError)?
-      .drain()
-      .map(|(validator, share)| {
-        Ok((
+        );
+      }
+      if !preprocesses.is_empty() {
+        Err(SignError::FrostError(FrostError::InvalidPreprocess(validator)))?;
+      }
+    }
+
+    let mut machines = vec![];
+    let mut shares = vec![];
+    for (attempt, commitments) in attempts.drain(..).zip(commitments.drain(..)) {
+      let (attempt, share) = attempt.sign(commitments, b"").map_err(SignError::FrostError)?;
+      machines.push(attempt);
+      shares.extend(share.serialize());
+    }
+
+    let mut all_shares = machines.iter().map(|_| HashMap::new()).collect::<Vec<_>>();
+    for (validator, shares) in network.round(shares).await.map_err(SignError::NetworkError)?.drain()
+    {
+      let mut shares = shares.as_slice();
+      for (attempt, all_shares) in machines.iter().zip(all_shares.iter_mut()) {
+        all_shares.insert(
           validator,
           attempt
-            .read_share::<&[u8]>(&mut share.as_ref())
+            .read_share::<&[u8]>(&mut shares)
             .map_err(|_| SignError::FrostError(FrostError::InvalidShare(validator)))?,
-        ))
-      })
-      .collect::<Result<HashMap<_, _>, _>>()?;
+        );
+      }
+      if !shares.is_empty() {
+        Err(SignError::FrostError(FrostError::InvalidShare(validator)))?;
+      }
+    }
 
-    let tx = attempt.complete(shares).map_err(SignError::FrostError)?;
+    // Complete every transaction before publishing any
+    let txs = machines
+      .drain(..)
+      .zip(all_shares.drain(..))
+      .map(|(attempt, shares)| attempt.complete(shares).map_err(SignError::FrostError))
+      .collect::<Result<Vec<_>, _>>()?;
 
-    self.coin.publish_transaction(&tx).await.map_err(SignError::CoinError)
+    let mut ids = vec![];
+    for tx in &txs {
+      ids.push(self.coin.publish_transaction(tx).await.map_err(SignError::CoinError)?);
+    }
+    Ok(ids)
   }
 }
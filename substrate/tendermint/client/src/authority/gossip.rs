@@ -1,48 +1,128 @@
-use std::sync::{Arc, RwLock};
+use std::{
+  sync::{Arc, RwLock},
+  time::Instant,
+  collections::HashMap,
+};
+
+use parity_scale_codec::{Encode, Decode};
 
-use sp_core::Decode;
 use sp_runtime::traits::{Hash, Header, Block};
 
 use sc_network::PeerId;
 use sc_network_gossip::{Validator, ValidatorContext, ValidationResult};
 
-use tendermint_machine::{ext::SignatureScheme, SignedMessage};
+use tendermint_machine::{
+  ext::{Signature, SignatureScheme},
+  SignedMessage, Heartbeat,
+};
+
+use crate::{MESSAGE_OVERHEAD, TendermintClient, validators::TendermintValidators};
+
+// Everything gossiped, tagged with its kind so one is never mistaken for the other
+#[derive(Clone, PartialEq, Debug, Encode, Decode)]
+pub(crate) enum GossipMessage<B: Block, S: Signature> {
+  Consensus(SignedMessage<u16, B, S>),
+  Heartbeat(Heartbeat<u16, S>),
+}
+
+// The size of the largest legitimate message, a proposal of a maximally sized block
+fn max_message_bytes(block_size_limit: usize) -> usize {
+  block_size_limit + usize::try_from(MESSAGE_OVERHEAD).unwrap()
+}
 
-use crate::{TendermintValidator, validators::TendermintValidators};
+// Whether data is larger than any legitimate message, so it can be discarded without decoding it
+fn oversized(max_message_bytes: usize, data: &[u8]) -> bool {
+  data.len() > max_message_bytes
+}
 
+// The topic for a block's messages, namespaced by the genesis hash of the chain
+fn block_topic<H: Hash>(genesis: H::Output, number: u64) -> H::Output {
+  H::hash(&[b"Tendermint Block Topic".as_ref(), genesis.as_ref(), &number.to_le_bytes()].concat())
+}
+
+// The gossip validator, generic to the signature scheme so it can be tested without a client
 #[derive(Clone)]
-pub(crate) struct TendermintGossip<T: TendermintValidator> {
+pub(crate) struct GossipValidator<B: Block, S: SignatureScheme<ValidatorId = u16>> {
+  // Hash identifying this chain, so chains sharing a network don't share topics
+  genesis: B::Hash,
   number: Arc<RwLock<u64>>,
-  signature_scheme: TendermintValidators<T>,
+  signature_scheme: S,
+  // When we last received a verified message from each validator
+  heard: Arc<RwLock<HashMap<u16, Instant>>>,
+  // The block number and timestamp of the latest heartbeat from each validator
+  heartbeats: Arc<RwLock<HashMap<u16, (u64, u64)>>>,
+  // Anything larger than this is discarded before spending any time decoding it
+  max_message_bytes: usize,
 }
+pub(crate) type TendermintGossip<T> =
+  GossipValidator<<T as TendermintClient>::Block, TendermintValidators<T>>;
+
+impl<B: Block, S: SignatureScheme<ValidatorId = u16>> GossipValidator<B, S> {
+  pub(crate) fn new(
+    genesis: B::Hash,
+    number: Arc<RwLock<u64>>,
+    signature_scheme: S,
+    heard: Arc<RwLock<HashMap<u16, Instant>>>,
+    block_size_limit: usize,
+  ) -> Self {
+    GossipValidator {
+      genesis,
+      number,
+      signature_scheme,
+      heard,
+      heartbeats: Arc::new(RwLock::new(HashMap::new())),
+      max_message_bytes: max_message_bytes(block_size_limit),
+    }
+  }
 
-impl<T: TendermintValidator> TendermintGossip<T> {
-  pub(crate) fn new(number: Arc<RwLock<u64>>, signature_scheme: TendermintValidators<T>) -> Self {
-    TendermintGossip { number, signature_scheme }
+  pub(crate) fn topic(genesis: B::Hash, number: u64) -> B::Hash {
+    block_topic::<<B::Header as Header>::Hashing>(genesis, number)
   }
 
-  pub(crate) fn topic(number: u64) -> <T::Block as Block>::Hash {
-    <<<T::Block as Block>::Header as Header>::Hashing as Hash>::hash(
-      &[b"Tendermint Block Topic".as_ref(), &number.to_le_bytes()].concat(),
+  // Heartbeats are gossiped on their own topic so they're never fed into consensus
+  pub(crate) fn heartbeat_topic(genesis: B::Hash) -> B::Hash {
+    <<B::Header as Header>::Hashing as Hash>::hash(
+      &[b"Tendermint Heartbeat Topic".as_ref(), genesis.as_ref()].concat(),
     )
   }
-}
 
-impl<T: TendermintValidator> Validator<T::Block> for TendermintGossip<T> {
-  fn validate(
-    &self,
-    _: &mut dyn ValidatorContext<T::Block>,
-    _: &PeerId,
-    data: &[u8],
-  ) -> ValidationResult<<T::Block as Block>::Hash> {
-    let msg = match SignedMessage::<
-      u16,
-      T::Block,
-      <TendermintValidators<T> as SignatureScheme>::Signature,
-    >::decode(&mut &*data)
-    {
-      Ok(msg) => msg,
-      Err(_) => return ValidationResult::Discard,
+  // Decode a gossiped message, requiring it be the entirety of the data
+  pub(crate) fn decode(mut data: &[u8]) -> Option<GossipMessage<B, S::Signature>> {
+    GossipMessage::<B, S::Signature>::decode(&mut data).ok().filter(|_| data.is_empty())
+  }
+
+  // Validate gossiped data, returning the topic it's for if it should be kept
+  fn validate_data(&self, data: &[u8]) -> ValidationResult<B::Hash> {
+    if oversized(self.max_message_bytes, data) {
+      return ValidationResult::Discard;
+    }
+
+    let msg = match Self::decode(data) {
+      Some(GossipMessage::Consensus(msg)) => msg,
+      Some(GossipMessage::Heartbeat(heartbeat)) => {
+        // Heartbeats are only accepted for the current block, or the next if we're behind
+        let number = *self.number.read().unwrap();
+        if (heartbeat.number.0 < number) || (heartbeat.number.0 > number.saturating_add(1)) {
+          return ValidationResult::Discard;
+        }
+        if !heartbeat.verify(&self.signature_scheme) {
+          return ValidationResult::Discard;
+        }
+
+        // Only the latest heartbeat from each validator is kept
+        {
+          let latest = (heartbeat.number.0, heartbeat.timestamp);
+          let mut heartbeats = self.heartbeats.write().unwrap();
+          if heartbeats.get(&heartbeat.sender).map(|prior| *prior >= latest).unwrap_or(false) {
+            return ValidationResult::Discard;
+          }
+          heartbeats.insert(heartbeat.sender, latest);
+        }
+
+        self.heard.write().unwrap().insert(heartbeat.sender, Instant::now());
+        return ValidationResult::ProcessAndKeep(Self::heartbeat_topic(self.genesis));
+      }
+      None => return ValidationResult::Discard,
     };
 
     if msg.block().0 < *self.number.read().unwrap() {
@@ -54,14 +134,207 @@ impl<T: TendermintValidator> Validator<T::Block> for TendermintGossip<T> {
     if !msg.verify_signature(&self.signature_scheme) {
       return ValidationResult::Discard;
     }
+    self.heard.write().unwrap().insert(msg.sender(), Instant::now());
 
-    ValidationResult::ProcessAndKeep(Self::topic(msg.block().0))
+    ValidationResult::ProcessAndKeep(Self::topic(self.genesis, msg.block().0))
   }
+}
 
-  fn message_expired<'a>(
-    &'a self,
-  ) -> Box<dyn FnMut(<T::Block as Block>::Hash, &[u8]) -> bool + 'a> {
+impl<B: Block, S: 'static + SignatureScheme<ValidatorId = u16>> Validator<B>
+  for GossipValidator<B, S>
+{
+  fn validate(
+    &self,
+    _: &mut dyn ValidatorContext<B>,
+    _: &PeerId,
+    data: &[u8],
+  ) -> ValidationResult<B::Hash> {
+    self.validate_data(data)
+  }
+
+  fn message_expired<'a>(&'a self) -> Box<dyn FnMut(B::Hash, &[u8]) -> bool + 'a> {
+    let genesis = self.genesis;
     let number = self.number.clone();
-    Box::new(move |topic, _| topic != Self::topic(*number.read().unwrap()))
+    let heartbeats = self.heartbeats.clone();
+    Box::new(move |topic, data| {
+      let number = *number.read().unwrap();
+      // Heartbeats expire once we've moved past the block they were for, or once superseded by a
+      // later heartbeat from the same validator
+      if topic == Self::heartbeat_topic(genesis) {
+        return match Self::decode(data) {
+          Some(GossipMessage::Heartbeat(heartbeat)) => {
+            (heartbeat.number.0 < number) ||
+              (heartbeats.read().unwrap().get(&heartbeat.sender) !=
+                Some(&(heartbeat.number.0, heartbeat.timestamp)))
+          }
+          _ => true,
+        };
+      }
+      topic != Self::topic(genesis, number)
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::{
+    sync::{Arc, RwLock},
+    time::Instant,
+    collections::HashMap,
+  };
+
+  use async_trait::async_trait;
+
+  use parity_scale_codec::{Encode, Decode};
+
+  use sp_core::H256;
+  use sp_runtime::{
+    traits::BlakeTwo256,
+    testing::{Header, Block as RawBlock, ExtrinsicWrapper},
+  };
+
+  use sc_network_gossip::ValidationResult;
+
+  use tendermint_machine::{
+    ext::{BlockNumber, RoundNumber, Signer, SignatureScheme, AggregateError},
+    SignedMessage, Heartbeat,
+  };
+
+  use super::{max_message_bytes, oversized, block_topic, GossipMessage, GossipValidator};
+
+  type TestBlock = RawBlock<ExtrinsicWrapper<u64>>;
+
+  // A signature scheme where a validator's signature is simply their ID
+  struct TestSigner(u16);
+  #[async_trait]
+  impl Signer for TestSigner {
+    type ValidatorId = u16;
+    type Signature = u16;
+
+    async fn validator_id(&self) -> Option<u16> {
+      Some(self.0)
+    }
+
+    async fn sign(&self, _: &[u8]) -> u16 {
+      self.0
+    }
+  }
+
+  #[derive(Clone)]
+  struct TestSignatureScheme;
+  impl SignatureScheme for TestSignatureScheme {
+    type ValidatorId = u16;
+    type Signature = u16;
+    type AggregateSignature = Vec<u16>;
+    type Signer = TestSigner;
+
+    fn verify(&self, validator: u16, _: &[u8], sig: &u16) -> bool {
+      validator == *sig
+    }
+
+    fn aggregate(sigs: &[u16]) -> Result<Vec<u16>, AggregateError> {
+      Ok(sigs.to_vec())
+    }
+
+    fn verify_aggregate(&self, validators: &[u16], _: &[u8], sigs: &Vec<u16>) -> bool {
+      validators == sigs
+    }
+  }
+
+  type TestGossip = GossipValidator<TestBlock, TestSignatureScheme>;
+
+  const GENESIS: H256 = H256::repeat_byte(1);
+
+  // Create a gossip validator working on the specified block, also returning who it's heard from
+  fn gossip(
+    number: u64,
+    block_size_limit: usize,
+  ) -> (TestGossip, Arc<RwLock<HashMap<u16, Instant>>>) {
+    let heard = Arc::new(RwLock::new(HashMap::new()));
+    let number = Arc::new(RwLock::new(number));
+    (TestGossip::new(GENESIS, number, TestSignatureScheme, heard.clone(), block_size_limit), heard)
+  }
+
+  // Encode the sender's proposal of a block with the specified amount of extrinsics
+  fn proposal(sender: u16, number: u64, extrinsics: u64) -> Vec<u8> {
+    let block = TestBlock {
+      header: Header::new_from_number(number),
+      extrinsics: (0 .. extrinsics).map(ExtrinsicWrapper::from).collect(),
+    };
+    // A Message with Data::Proposal(None, block), followed by its signature
+    let msg = (sender, BlockNumber(number), RoundNumber(0), 0u8, None::<RoundNumber>, block, sender)
+      .encode();
+    let msg = SignedMessage::decode(&mut msg.as_slice()).unwrap();
+    GossipMessage::<TestBlock, u16>::Consensus(msg).encode()
+  }
+
+  fn heartbeat(sender: u16, number: u64, timestamp: u64, sig: u16) -> Vec<u8> {
+    let heartbeat = Heartbeat { sender, number: BlockNumber(number), timestamp, sig };
+    GossipMessage::<TestBlock, u16>::Heartbeat(heartbeat).encode()
+  }
+
+  #[test]
+  fn topics_namespaced_by_genesis() {
+    let a = H256::repeat_byte(1);
+    let b = H256::repeat_byte(2);
+    assert_eq!(block_topic::<BlakeTwo256>(a, 1), block_topic::<BlakeTwo256>(a, 1));
+    assert_ne!(block_topic::<BlakeTwo256>(a, 1), block_topic::<BlakeTwo256>(b, 1));
+    assert_ne!(block_topic::<BlakeTwo256>(a, 1), block_topic::<BlakeTwo256>(a, 2));
+  }
+
+  #[test]
+  fn oversized_discarded() {
+    let max = max_message_bytes(1024);
+    assert!(max > 1024);
+    assert!(!oversized(max, &vec![0; max]));
+    assert!(oversized(max, &vec![0; max + 1]));
+  }
+
+  #[test]
+  fn heartbeats_routed_to_heartbeat_topic() {
+    let (gossip, heard) = gossip(5, 1024);
+    let heartbeat_topic = TestGossip::heartbeat_topic(GENESIS);
+    assert_ne!(heartbeat_topic, TestGossip::topic(GENESIS, 5));
+    assert_ne!(heartbeat_topic, TestGossip::topic(GENESIS, 6));
+
+    // Heartbeats for the current and next block are kept under the heartbeat topic, never any
+    // block's topic, so they never reach consensus
+    for number in [5, 6] {
+      match gossip.validate_data(&heartbeat(0, number, number, 0)) {
+        ValidationResult::ProcessAndKeep(topic) => assert_eq!(topic, heartbeat_topic),
+        _ => panic!("valid heartbeat wasn't kept"),
+      }
+    }
+    assert!(heard.read().unwrap().contains_key(&0));
+    // Nor are they ever decoded as a consensus message
+    let decoded = TestGossip::decode(&heartbeat(0, 5, 5, 0));
+    assert!(matches!(decoded, Some(GossipMessage::Heartbeat(_))));
+
+    // Heartbeats which are stale, too far ahead, superseded, or forged are discarded
+    for data in [
+      heartbeat(1, 4, 0, 1),
+      heartbeat(1, 7, 0, 1),
+      heartbeat(1, u64::MAX, 0, 1),
+      heartbeat(0, 6, 6, 0),
+      heartbeat(1, 5, 0, 0),
+    ] {
+      assert!(matches!(gossip.validate_data(&data), ValidationResult::Discard));
+    }
+    assert!(!heard.read().unwrap().contains_key(&1));
+  }
+
+  #[test]
+  fn oversized_message_discarded() {
+    let data = proposal(0, 5, 128);
+    let limit = data.len() - max_message_bytes(0);
+
+    // A valid proposal is kept when within the limit
+    let (gossip, _) = gossip(5, limit);
+    assert!(matches!(gossip.validate_data(&data), ValidationResult::ProcessAndKeep(_)));
+
+    // Yet discarded, without being decoded, when one byte over it
+    let (gossip, heard) = gossip(5, limit - 1);
+    assert!(matches!(gossip.validate_data(&data), ValidationResult::Discard));
+    assert!(heard.read().unwrap().is_empty());
   }
 }
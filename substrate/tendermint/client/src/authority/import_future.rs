@@ -35,9 +35,12 @@ impl<B: Block> Link<B> for ValidateLink<B> {
       match results.swap_remove(0).0 {
         Ok(_) => Ok(()),
         Err(BlockImportError::Other(Error::Other(err))) => Err(
-          err.downcast::<BlockError>().map(|boxed| *boxed.as_ref()).unwrap_or(BlockError::Fatal),
+          err
+            .downcast::<BlockError>()
+            .map(|boxed| *boxed.as_ref())
+            .unwrap_or(BlockError::Fatal(None)),
         ),
-        _ => Err(BlockError::Fatal),
+        _ => Err(BlockError::Fatal(None)),
       },
     ));
   }
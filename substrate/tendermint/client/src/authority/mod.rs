@@ -9,7 +9,7 @@ use async_trait::async_trait;
 use log::{debug, warn, error};
 
 use futures::{
-  SinkExt, StreamExt,
+  FutureExt, SinkExt, StreamExt,
   lock::Mutex,
   channel::mpsc::{self, UnboundedSender},
 };
@@ -30,11 +30,11 @@ use sc_client_api::{BlockBackend, Finalizer, BlockchainEvents};
 use sc_network::{ProtocolName, NetworkBlock};
 use sc_network_gossip::GossipEngine;
 
-use substrate_prometheus_endpoint::Registry;
+use substrate_prometheus_endpoint::{register, Counter, U64, Registry};
 
 use tendermint_machine::{
-  ext::{BlockError, BlockNumber, Commit, SignatureScheme, Network},
-  SignedMessage, TendermintMachine, TendermintHandle,
+  ext::{BlockError, BlockNumber, RoundNumber, Commit, SignatureScheme, SlashReason, Network},
+  SignedMessage, Heartbeat, TendermintMachine, TendermintHandle,
 };
 
 use crate::{
@@ -44,7 +44,7 @@ use crate::{
 };
 
 mod gossip;
-use gossip::TendermintGossip;
+use gossip::{GossipMessage, TendermintGossip};
 
 mod import_future;
 use import_future::ImportFuture;
@@ -64,10 +64,27 @@ struct ActiveAuthority<T: TendermintValidator> {
   gossip: UnboundedSender<
     SignedMessage<u16, T::Block, <TendermintValidators<T> as SignatureScheme>::Signature>,
   >,
+  // Outgoing heartbeat queue, kept distinct as heartbeats are gossiped on their own topic
+  heartbeats:
+    UnboundedSender<Heartbeat<u16, <TendermintValidators<T> as SignatureScheme>::Signature>>,
 
   // Block producer
   env: Arc<Mutex<T::Environment>>,
   announce: T::Network,
+
+  // Amount of rounds which ended due to the precommit timeout
+  precommit_timeouts: Option<Counter<U64>>,
+}
+
+// Register the counter of rounds which ended due to the precommit timeout
+fn precommit_timeouts_counter(registry: &Registry) -> Option<Counter<U64>> {
+  Counter::new(
+    "tendermint_precommit_timeouts",
+    "Amount of rounds ended due to the precommit timeout",
+  )
+  .and_then(|counter| register(counter, registry))
+  .map_err(|e| warn!(target: "tendermint", "Failed to register metric: {}", e))
+  .ok()
 }
 
 /// Tendermint Authority. Participates in the block proposal and voting process.
@@ -134,12 +151,18 @@ impl<T: TendermintValidator> TendermintAuthority<T> {
     // a firm bound. It's not worth having a backlog crash the node since we aren't constrained
     let (new_block_event_send, mut new_block_event_recv) = mpsc::unbounded();
     let (msg_send, mut msg_recv) = mpsc::unbounded();
+    let (heartbeat_send, mut heartbeat_recv) = mpsc::unbounded();
 
     // Move the env into an Arc
     let env = Arc::new(Mutex::new(env));
 
+    let precommit_timeouts = registry.and_then(precommit_timeouts_counter);
+
+    // Hash of the genesis block, used to make our gossip topics specific to this chain
+    let genesis_hash = import.client.info().genesis_hash;
+
     // Scoped so the temporary variables used here don't leak
-    let (block_in_progress, mut gossip, TendermintHandle { mut step, mut messages, machine }) = {
+    let (block_in_progress, mut gossip, TendermintHandle { mut step, mut messages, machine, .. }) = {
       // Get the info necessary to spawn the machine
       let info = import.client.info();
 
@@ -187,9 +210,12 @@ impl<T: TendermintValidator> TendermintAuthority<T> {
           block_in_progress: block_in_progress.clone(),
           new_block_event: new_block_event_send,
           gossip: msg_send,
+          heartbeats: heartbeat_send,
 
           env: env.clone(),
           announce: network.clone(),
+
+          precommit_timeouts,
         }),
       };
 
@@ -202,7 +228,13 @@ impl<T: TendermintValidator> TendermintAuthority<T> {
       let gossip = GossipEngine::new(
         network,
         protocol,
-        Arc::new(TendermintGossip::new(block_in_progress.clone(), import.validators.clone())),
+        Arc::new(TendermintGossip::<T>::new(
+          genesis_hash,
+          block_in_progress.clone(),
+          import.validators.clone(),
+          import.heard.clone(),
+          T::PROPOSED_BLOCK_SIZE_LIMIT,
+        )),
         registry,
       );
 
@@ -212,11 +244,11 @@ impl<T: TendermintValidator> TendermintAuthority<T> {
         TendermintMachine::new(authority, BlockNumber(last_block), last_time, proposal).await,
       )
     };
-    spawner.spawn_essential("machine", Some("tendermint"), Box::pin(machine.run()));
+    spawner.spawn_essential("machine", Some("tendermint"), Box::pin(machine.run().map(|_| ())));
 
     // Start receiving messages about the Tendermint process for this block
-    let mut gossip_recv =
-      gossip.messages_for(TendermintGossip::<T>::topic(*block_in_progress.read().unwrap()));
+    let mut gossip_recv = gossip
+      .messages_for(TendermintGossip::<T>::topic(genesis_hash, *block_in_progress.read().unwrap()));
 
     // Get finality events from Substrate
     let mut finality = import.client.finality_notification_stream();
@@ -251,7 +283,8 @@ impl<T: TendermintValidator> TendermintAuthority<T> {
               }
               let next_block = number + 1;
               *block_in_progress = next_block;
-              gossip_recv = gossip.messages_for(TendermintGossip::<T>::topic(next_block));
+              gossip_recv =
+                gossip.messages_for(TendermintGossip::<T>::topic(genesis_hash, next_block));
             }
 
             let justifications = import.client.justifications(notif.hash).unwrap().unwrap();
@@ -277,7 +310,7 @@ impl<T: TendermintValidator> TendermintAuthority<T> {
         new_block = new_block_event_recv.next() => {
           if new_block.is_some() {
             gossip_recv = gossip.messages_for(
-              TendermintGossip::<T>::topic(*block_in_progress.read().unwrap())
+              TendermintGossip::<T>::topic(genesis_hash, *block_in_progress.read().unwrap())
             );
           } else {
             debug!(
@@ -292,8 +325,8 @@ impl<T: TendermintValidator> TendermintAuthority<T> {
         // Message to broadcast
         msg = msg_recv.next() => {
           if let Some(msg) = msg {
-            let topic = TendermintGossip::<T>::topic(msg.block().0);
-            gossip.gossip_message(topic, msg.encode(), false);
+            let topic = TendermintGossip::<T>::topic(genesis_hash, msg.block().0);
+            gossip.gossip_message(topic, GossipMessage::Consensus(msg).encode(), false);
           } else {
             debug!(
               target: "tendermint",
@@ -304,16 +337,31 @@ impl<T: TendermintValidator> TendermintAuthority<T> {
           }
         },
 
+        // Heartbeat to broadcast
+        heartbeat = heartbeat_recv.next() => {
+          if let Some(heartbeat) = heartbeat {
+            let topic = TendermintGossip::<T>::heartbeat_topic(genesis_hash);
+            gossip.gossip_message(topic, GossipMessage::Heartbeat(heartbeat).encode(), false);
+          } else {
+            debug!(
+              target: "tendermint",
+              "Machine's heartbeat channel shut down. {}",
+              "Is the node shutting down?"
+            );
+            break;
+          }
+        },
+
         // Received a message
         msg = gossip_recv.next() => {
           if let Some(msg) = msg {
             messages.send(
-              match SignedMessage::decode(&mut msg.message.as_ref()) {
-                Ok(msg) => msg,
-                Err(e) => {
+              match TendermintGossip::<T>::decode(&msg.message) {
+                Some(GossipMessage::Consensus(msg)) => msg,
+                _ => {
                   // This is guaranteed to be valid thanks to to the gossip validator, assuming
                   // that pipeline is correct. This doesn't panic as a hedge
-                  error!(target: "tendermint", "Couldn't decode valid message: {}", e);
+                  error!(target: "tendermint", "Couldn't decode valid message");
                   continue;
                 }
               }
@@ -342,6 +390,8 @@ impl<T: TendermintValidator> Network for TendermintAuthority<T> {
   const BLOCK_PROCESSING_TIME: u32 = T::BLOCK_PROCESSING_TIME_IN_SECONDS;
   const LATENCY_TIME: u32 = T::LATENCY_TIME_IN_SECONDS;
 
+  const HEARTBEATS: bool = true;
+
   fn signer(&self) -> TendermintSigner<T> {
     self.active.as_ref().unwrap().signer.clone()
   }
@@ -367,9 +417,37 @@ impl<T: TendermintValidator> Network for TendermintAuthority<T> {
     }
   }
 
-  async fn slash(&mut self, validator: u16) {
+  async fn broadcast_heartbeat(
+    &mut self,
+    heartbeat: Heartbeat<u16, <TendermintValidators<T> as SignatureScheme>::Signature>,
+  ) {
+    if self.active.as_mut().unwrap().heartbeats.unbounded_send(heartbeat).is_err() {
+      warn!(
+        target: "tendermint",
+        "Attempted to broadcast a heartbeat except the heartbeat channel is closed. {}",
+        "Is the node shutting down?"
+      );
+    }
+  }
+
+  async fn slash(&mut self, validator: u16, reason: SlashReason) {
     // TODO
-    error!("slashing {}, if this is a local network, this shouldn't happen", validator);
+    error!(
+      "slashing {} for {:?}, if this is a local network, this shouldn't happen",
+      validator, reason,
+    );
+  }
+
+  fn precommit_timeout(&mut self, block: BlockNumber, round: RoundNumber) {
+    debug!(
+      target: "tendermint",
+      "Block {} round {} ended due to its precommit timeout",
+      block.0,
+      round.0,
+    );
+    if let Some(counter) = self.active.as_ref().unwrap().precommit_timeouts.as_ref() {
+      counter.inc();
+    }
   }
 
   // The Tendermint machine will call add_block for any block which is committed to, regardless of
@@ -492,3 +570,26 @@ impl<T: TendermintValidator> Network for TendermintAuthority<T> {
     Some(self.get_proposal(block.header()).await)
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use substrate_prometheus_endpoint::Registry;
+
+  use super::precommit_timeouts_counter;
+
+  #[test]
+  fn precommit_timeouts_metric() {
+    let registry = Registry::new();
+    let counter = precommit_timeouts_counter(&registry).unwrap();
+    counter.inc();
+    counter.inc();
+
+    let families = registry.gather();
+    let family =
+      families.iter().find(|family| family.get_name() == "tendermint_precommit_timeouts").unwrap();
+    assert_eq!(family.get_metric()[0].get_counter().get_value(), 2.0);
+
+    // The metric can't be registered twice
+    assert!(precommit_timeouts_counter(&registry).is_none());
+  }
+}
@@ -33,7 +33,15 @@ pub use authority::TendermintAuthority;
 pub const CONSENSUS_ID: [u8; 4] = *b"tend";
 pub(crate) const KEY_TYPE_ID: KeyTypeId = KeyTypeId(CONSENSUS_ID);
 
-const PROTOCOL_NAME: &str = "/tendermint/1";
+// Version 2 tags gossiped messages with whether they're for consensus or a heartbeat
+const PROTOCOL_NAME: &str = "/tendermint/2";
+
+// The extra 512 bytes is for the additional data part of Tendermint
+// Even with BLS, that should just be 161 bytes in the worst case, for a perfect messaging scheme
+// While 256 bytes would suffice there, it's unknown if any LibP2P overhead exists nor if
+// anything here will be perfect. Considering this is miniscule compared to the block size, it's
+// better safe than sorry.
+pub(crate) const MESSAGE_OVERHEAD: u64 = 512;
 
 pub fn protocol_name<Hash: AsRef<[u8]>>(genesis: Hash, fork: Option<&str>) -> ProtocolName {
   let mut name = format!("/{}", hex::encode(genesis.as_ref()));
@@ -45,12 +53,7 @@ pub fn protocol_name<Hash: AsRef<[u8]>>(genesis: Hash, fork: Option<&str>) -> Pr
 }
 
 pub fn set_config(protocol: ProtocolName, block_size: u64) -> NonDefaultSetConfig {
-  // The extra 512 bytes is for the additional data part of Tendermint
-  // Even with BLS, that should just be 161 bytes in the worst case, for a perfect messaging scheme
-  // While 256 bytes would suffice there, it's unknown if any LibP2P overhead exists nor if
-  // anything here will be perfect. Considering this is miniscule compared to the block size, it's
-  // better safe than sorry.
-  let mut cfg = NonDefaultSetConfig::new(protocol, block_size + 512);
+  let mut cfg = NonDefaultSetConfig::new(protocol, block_size + MESSAGE_OVERHEAD);
   cfg.allow_non_reserved(25, 25);
   cfg
 }
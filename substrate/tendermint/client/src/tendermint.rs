@@ -1,6 +1,7 @@
 use std::{
   sync::{Arc, RwLock},
-  collections::HashSet,
+  time::{Instant, Duration},
+  collections::{HashSet, HashMap},
 };
 
 use log::{debug, warn};
@@ -28,6 +29,15 @@ use crate::{
   TendermintImportQueue, authority::TendermintAuthority,
 };
 
+// The validators heard from within the specified duration of now
+fn recently_heard(heard: &HashMap<u16, Instant>, now: Instant, within: Duration) -> HashSet<u16> {
+  heard
+    .iter()
+    .filter(|(_, last)| now.saturating_duration_since(**last) <= within)
+    .map(|(validator, _)| *validator)
+    .collect()
+}
+
 type InstantiatedTendermintImportQueue<T> = TendermintImportQueue<
   <T as TendermintClient>::Block,
   <T as TendermintClient>::BackendTransaction,
@@ -50,6 +60,9 @@ pub struct TendermintImport<T: TendermintValidator> {
   // if they're proposed again, we see if our perception has changed
   pub(crate) recheck: Arc<RwLock<HashSet<<T::Block as Block>::Hash>>>,
 
+  // When we last received a verified message from each validator, as observed by the gossip layer
+  pub(crate) heard: Arc<RwLock<HashMap<u16, Instant>>>,
+
   pub(crate) client: Arc<T::Client>,
   pub(crate) queue: Arc<AsyncRwLock<Option<InstantiatedTendermintImportQueue<T>>>>,
 }
@@ -65,6 +78,8 @@ impl<T: TendermintValidator> Clone for TendermintImport<T> {
       importing_block: self.importing_block.clone(),
       recheck: self.recheck.clone(),
 
+      heard: self.heard.clone(),
+
       client: self.client.clone(),
       queue: self.queue.clone(),
     }
@@ -82,11 +97,20 @@ impl<T: TendermintValidator> TendermintImport<T> {
       importing_block: Arc::new(RwLock::new(None)),
       recheck: Arc::new(RwLock::new(HashSet::new())),
 
+      heard: Arc::new(RwLock::new(HashMap::new())),
+
       client,
       queue: Arc::new(AsyncRwLock::new(None)),
     }
   }
 
+  /// Validators we've received a verified message from within the specified duration.
+  /// Maintaining connections to high-weight validators not in this set should be prioritized, as
+  /// reaching quorum quickly depends on hearing from them.
+  pub fn recently_heard(&self, within: Duration) -> HashSet<u16> {
+    recently_heard(&self.heard.read().unwrap(), Instant::now(), within)
+  }
+
   pub(crate) async fn inherent_data(&self, parent: <T::Block as Block>::Hash) -> InherentData {
     match self
       .providers
@@ -122,13 +146,13 @@ impl<T: TendermintValidator> TendermintImport<T> {
       .client
       .runtime_api()
       .check_inherents(&BlockId::Hash(self.client.info().finalized_hash), block, inherent_data)
-      .map_err(|_| Error::Other(BlockError::Fatal.into()))?;
+      .map_err(|_| Error::Other(BlockError::Fatal(None).into()))?;
 
     if err.ok() {
       self.recheck.write().unwrap().remove(&hash);
       Ok(())
     } else if err.fatal_error() {
-      Err(Error::Other(BlockError::Fatal.into()))
+      Err(Error::Other(BlockError::Fatal(None).into()))
     } else {
       debug!(target: "tendermint", "Proposed block has temporally wrong inherents");
       self.recheck.write().unwrap().insert(hash);
@@ -245,3 +269,25 @@ impl<T: TendermintValidator> TendermintImport<T> {
     Ok(())
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use std::{
+    time::{Instant, Duration},
+    collections::{HashSet, HashMap},
+  };
+
+  use super::recently_heard;
+
+  #[test]
+  fn recently_heard_senders() {
+    // Validators 0 and 2 were heard from two seconds ago, validator 3 ten seconds ago, and
+    // validator 1 never
+    let start = Instant::now();
+    let recent = start + Duration::from_secs(8);
+    let now = start + Duration::from_secs(10);
+    let heard = HashMap::from([(0, recent), (2, recent), (3, start)]);
+    assert_eq!(recently_heard(&heard, now, Duration::from_secs(5)), HashSet::from([0, 2]));
+    assert_eq!(recently_heard(&heard, now, Duration::from_secs(10)), HashSet::from([0, 2, 3]));
+  }
+}
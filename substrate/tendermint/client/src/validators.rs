@@ -15,7 +15,9 @@ use sp_api::{BlockId, ProvideRuntimeApi};
 
 use sc_client_api::HeaderBackend;
 
-use tendermint_machine::ext::{BlockNumber, RoundNumber, Weights, Signer, SignatureScheme};
+use tendermint_machine::ext::{
+  BlockNumber, RoundNumber, Weights, Signer, SignatureScheme, AggregateError,
+};
 
 use sp_tendermint::TendermintApi;
 
@@ -152,8 +154,12 @@ impl<T: TendermintClient> SignatureScheme for TendermintValidators<T> {
     self.0.read().unwrap().lookup[usize::try_from(validator).unwrap()].verify(&msg, sig)
   }
 
-  fn aggregate(sigs: &[Signature]) -> Vec<Signature> {
-    sigs.to_vec()
+  fn aggregate(sigs: &[Signature]) -> Result<Vec<Signature>, AggregateError> {
+    Ok(sigs.to_vec())
+  }
+
+  fn split_aggregate(sigs: &Vec<Signature>) -> Option<Vec<Signature>> {
+    Some(sigs.clone())
   }
 
   fn verify_aggregate(&self, validators: &[u16], msg: &[u8], sigs: &Vec<Signature>) -> bool {
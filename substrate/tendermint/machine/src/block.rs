@@ -1,23 +1,63 @@
 use std::{
   sync::Arc,
+  time::Instant,
   collections::{HashSet, HashMap},
 };
 
+use futures::future;
+use tokio::time::sleep;
+
 use crate::{
   time::CanonicalInstant,
-  ext::{RoundNumber, BlockNumber, Block, Network},
+  ext::{RoundNumber, BlockNumber, Block, SlashReason, Network},
   round::RoundData,
   message_log::MessageLog,
   Step, Data, DataFor, Message, MessageFor,
 };
 
+// Populate the end times up to the specified round, calculating each from the end time of the
+// round prior
+// We only start a round once we have all the prior time data, so this normally starts from the
+// round prior to the specified one. Instead, this walks back to the highest round with an end time,
+// so a gap (such as a pruned round) doesn't cause a panic. If there's no end time for any round
+// prior, this instead walks forward to the lowest round with an end time (which will be the
+// current round if nothing else), calculating each end time from the end time of the round after
+fn populate_end_times<T: Copy>(
+  end_times: &mut HashMap<RoundNumber, T>,
+  round: RoundNumber,
+  current: (RoundNumber, T),
+  end_time: impl Fn(RoundNumber, T) -> T,
+  prior_end_time: impl Fn(RoundNumber, T) -> T,
+) {
+  if end_times.contains_key(&round) {
+    return;
+  }
+  end_times.entry(current.0).or_insert(current.1);
+
+  if let Some(anchor) = end_times.keys().map(|r| r.0).filter(|r| *r < round.0).max() {
+    for r in (anchor + 1) ..= round.0 {
+      let prior = end_times[&RoundNumber(r - 1)];
+      end_times.insert(RoundNumber(r), end_time(RoundNumber(r), prior));
+    }
+  } else {
+    // The current round is always present, and not the specified round, so this must exist
+    let anchor = end_times.keys().map(|r| r.0).filter(|r| *r > round.0).min().unwrap();
+    for r in (round.0 .. anchor).rev() {
+      let next = end_times[&RoundNumber(r + 1)];
+      end_times.insert(RoundNumber(r), prior_end_time(RoundNumber(r + 1), next));
+    }
+  }
+}
+
 pub(crate) struct BlockData<N: Network> {
   pub(crate) number: BlockNumber,
   pub(crate) validator_id: Option<N::ValidatorId>,
+  // The time of the prior block, if known, which proposed blocks may not be timestamped before
+  pub(crate) prior_time: Option<u64>,
   pub(crate) proposal: Option<N::Block>,
 
   pub(crate) log: MessageLog<N>,
-  pub(crate) slashes: HashSet<N::ValidatorId>,
+  pub(crate) slashes: HashSet<(N::ValidatorId, SlashReason)>,
   // We track the end times of each round for two reasons:
   // 1) Knowing the start time of the next round
   // 2) Validating precommits, which include the end time of the round which produced it
@@ -28,6 +68,9 @@ pub(crate) struct BlockData<N: Network> {
 
   pub(crate) locked: Option<(RoundNumber, <N::Block as Block>::Id)>,
   pub(crate) valid: Option<(RoundNumber, N::Block)>,
+
+  // Proposals whose blocks were too far in the future when received, and when to retry them
+  pub(crate) delayed: HashMap<RoundNumber, (Instant, MessageFor<N>)>,
 }
 
 impl<N: Network> BlockData<N> {
@@ -35,11 +78,13 @@ impl<N: Network> BlockData<N> {
     weights: Arc<N::Weights>,
     number: BlockNumber,
     validator_id: Option<N::ValidatorId>,
+    prior_time: Option<u64>,
     proposal: Option<N::Block>,
   ) -> BlockData<N> {
     BlockData {
       number,
       validator_id,
+      prior_time,
       proposal,
 
       log: MessageLog::new(weights),
@@ -51,6 +96,8 @@ impl<N: Network> BlockData<N> {
 
       locked: None,
       valid: None,
+
+      delayed: HashMap::new(),
     }
   }
 
@@ -62,18 +109,43 @@ impl<N: Network> BlockData<N> {
     self.round.as_mut().unwrap()
   }
 
+  pub(crate) async fn delayed_future(&self) {
+    if let Some(time) = self.delayed.values().map(|(time, _)| *time).min() {
+      sleep(time.saturating_duration_since(Instant::now())).await;
+    } else {
+      future::pending::<()>().await;
+    }
+  }
+
+  // Take the delayed proposal which is next to be retried
+  pub(crate) fn take_delayed(&mut self) -> Option<MessageFor<N>> {
+    let round = *self.delayed.iter().min_by_key(|(_, (time, _))| *time)?.0;
+    self.delayed.remove(&round).map(|(_, msg)| msg)
+  }
+
   // Populate the end time up to the specified round
   // This is generally used when moving to the next round, where this will only populate one time,
   // yet is also used when jumping rounds (when 33% of the validators are on a round ahead of us)
   pub(crate) fn populate_end_time(&mut self, round: RoundNumber) {
-    // Starts from the current round since we only start the current round once we have have all
-    // the prior time data
-    for r in (self.round().number.0 + 1) ..= round.0 {
-      self.end_time.insert(
-        RoundNumber(r),
-        RoundData::<N>::new(RoundNumber(r), self.end_time[&RoundNumber(r - 1)]).end_time(),
-      );
-    }
+    let current = (self.round().number, self.round().end_time());
+    populate_end_times(
+      &mut self.end_time,
+      round,
+      current,
+      |round, prior| RoundData::<N>::new(round, prior).end_time(),
+      RoundData::<N>::start_time,
+    );
+  }
+
+  // Populate the end time of the round prior to the specified round, from the start time of the
+  // first round, without populating every round prior
+  // This is used when resuming, where populating every round prior would take time linear to the
+  // round resumed at
+  pub(crate) fn populate_prior_end_time(&mut self, round: RoundNumber) {
+    debug_assert_eq!(self.round().number.0, 0);
+    let prior = RoundNumber(round.0 - 1);
+    let end_time = RoundData::<N>::end_time_since_first(self.round().start_time, prior);
+    self.end_time.insert(prior, end_time);
   }
 
   // Start a new round. Optionally takes in the time for when this is the first round, and the time
@@ -137,3 +209,79 @@ impl<N: Network> BlockData<N> {
     })
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use std::collections::HashMap;
+
+  use crate::ext::RoundNumber;
+
+  use super::populate_end_times;
+
+  // Each round ends 10 after the prior round's end
+  fn next(_: RoundNumber, prior: u64) -> u64 {
+    prior + 10
+  }
+  fn prior(_: RoundNumber, next: u64) -> u64 {
+    next - 10
+  }
+
+  #[test]
+  fn populate_end_times_gap() {
+    // Rounds 0 and 1 are known, yet round 2 was lost
+    let mut end_times = HashMap::from([(RoundNumber(0), 10), (RoundNumber(1), 20)]);
+    populate_end_times(&mut end_times, RoundNumber(4), (RoundNumber(1), 20), next, prior);
+    assert_eq!(
+      end_times,
+      HashMap::from([
+        (RoundNumber(0), 10),
+        (RoundNumber(1), 20),
+        (RoundNumber(2), 30),
+        (RoundNumber(3), 40),
+        (RoundNumber(4), 50),
+      ])
+    );
+
+    // A round below the highest known round is already populated, and left as-is
+    populate_end_times(&mut end_times, RoundNumber(3), (RoundNumber(4), 50), next, prior);
+    assert_eq!(end_times.len(), 5);
+
+    // Without any end times, population starts from the current round
+    let mut end_times = HashMap::new();
+    populate_end_times(&mut end_times, RoundNumber(3), (RoundNumber(2), 30), next, prior);
+    assert_eq!(end_times, HashMap::from([(RoundNumber(2), 30), (RoundNumber(3), 40)]));
+  }
+
+  #[test]
+  fn populate_end_times_pruned() {
+    // Round 2 was pruned, yet rounds 0, 1, 3, and 4 are known
+    let mut end_times = HashMap::from([
+      (RoundNumber(0), 10),
+      (RoundNumber(1), 20),
+      (RoundNumber(3), 40),
+      (RoundNumber(4), 50),
+    ]);
+    populate_end_times(&mut end_times, RoundNumber(2), (RoundNumber(4), 50), next, prior);
+    assert_eq!(end_times[&RoundNumber(2)], 30);
+    assert_eq!(end_times.len(), 5);
+  }
+
+  #[test]
+  fn populate_end_times_behind_current() {
+    // Only the current round is known, as when resuming, and an earlier round is requested
+    let mut end_times = HashMap::from([(RoundNumber(3), 40)]);
+    populate_end_times(&mut end_times, RoundNumber(1), (RoundNumber(3), 40), next, prior);
+    assert_eq!(
+      end_times,
+      HashMap::from([(RoundNumber(1), 20), (RoundNumber(2), 30), (RoundNumber(3), 40)])
+    );
+
+    // Nothing is known at all, with the current round ahead of the requested one
+    let mut end_times = HashMap::new();
+    populate_end_times(&mut end_times, RoundNumber(0), (RoundNumber(2), 30), next, prior);
+    assert_eq!(
+      end_times,
+      HashMap::from([(RoundNumber(0), 10), (RoundNumber(1), 20), (RoundNumber(2), 30)])
+    );
+  }
+}
@@ -1,21 +1,22 @@
 use core::{hash::Hash, fmt::Debug};
-use std::{sync::Arc, collections::HashSet};
+use std::sync::Arc;
 
 use async_trait::async_trait;
 use thiserror::Error;
 
 use parity_scale_codec::{Encode, Decode};
 
-use crate::{SignedMessageFor, commit_msg};
+use crate::{SignedMessageFor, HeartbeatFor, commit_msg};
 
 /// An alias for a series of traits required for a type to be usable as a validator ID,
 /// automatically implemented for all types satisfying those traits.
 pub trait ValidatorId:
-  Send + Sync + Clone + Copy + PartialEq + Eq + Hash + Debug + Encode + Decode
+  Send + Sync + Clone + Copy + PartialEq + Eq + PartialOrd + Ord + Hash + Debug + Encode + Decode
 {
 }
-impl<V: Send + Sync + Clone + Copy + PartialEq + Eq + Hash + Debug + Encode + Decode> ValidatorId
-  for V
+impl<
+    V: Send + Sync + Clone + Copy + PartialEq + Eq + PartialOrd + Ord + Hash + Debug + Encode + Decode,
+  > ValidatorId for V
 {
 }
 
@@ -33,6 +34,13 @@ pub struct BlockNumber(pub u64);
 #[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Encode, Decode)]
 pub struct RoundNumber(pub u32);
 
+impl RoundNumber {
+  /// The round after this one, or None if the round number would overflow.
+  pub fn next(self) -> Option<RoundNumber> {
+    self.0.checked_add(1).map(RoundNumber)
+  }
+}
+
 /// A signer for a validator.
 #[async_trait]
 pub trait Signer: Send + Sync {
@@ -80,8 +88,14 @@ pub trait SignatureScheme: Send + Sync {
   #[must_use]
   fn verify(&self, validator: Self::ValidatorId, msg: &[u8], sig: &Self::Signature) -> bool;
 
-  /// Aggregate signatures.
-  fn aggregate(sigs: &[Self::Signature]) -> Self::AggregateSignature;
+  /// Aggregate signatures. Signatures which were individually verified may still fail to
+  /// aggregate, in which case the index of the offending signature is returned.
+  fn aggregate(sigs: &[Self::Signature]) -> Result<Self::AggregateSignature, AggregateError>;
+  /// Split an aggregate signature back into the signatures it aggregated, in order, if the
+  /// scheme allows doing so.
+  fn split_aggregate(_sig: &Self::AggregateSignature) -> Option<Vec<Self::Signature>> {
+    None
+  }
   /// Verify an aggregate signature for the list of signers.
   #[must_use]
   fn verify_aggregate(
@@ -102,10 +116,14 @@ impl<S: SignatureScheme> SignatureScheme for Arc<S> {
     self.as_ref().verify(validator, msg, sig)
   }
 
-  fn aggregate(sigs: &[Self::Signature]) -> Self::AggregateSignature {
+  fn aggregate(sigs: &[Self::Signature]) -> Result<Self::AggregateSignature, AggregateError> {
     S::aggregate(sigs)
   }
 
+  fn split_aggregate(sig: &Self::AggregateSignature) -> Option<Vec<Self::Signature>> {
+    S::split_aggregate(sig)
+  }
+
   #[must_use]
   fn verify_aggregate(
     &self,
@@ -123,12 +141,65 @@ impl<S: SignatureScheme> SignatureScheme for Arc<S> {
 pub struct Commit<S: SignatureScheme> {
   /// End time of the round which created this commit, used as the start time of the next block.
   pub end_time: u64,
-  /// Validators participating in the signature.
+  /// Validators participating in the signature, sorted in ascending order.
   pub validators: Vec<S::ValidatorId>,
   /// Aggregate signature.
   pub signature: S::AggregateSignature,
 }
 
+impl<S: SignatureScheme> Commit<S> {
+  /// Build a commit from a set of precommit signatures, each from a distinct validator, for the
+  /// round with the specified end time. The precommits may be in any order. If a signature fails
+  /// to aggregate, the error's index is of it within the precommits passed.
+  pub fn build(
+    end_time: u64,
+    precommits: &[(S::ValidatorId, S::Signature)],
+  ) -> Result<Commit<S>, AggregateError> {
+    let mut sorted = precommits.iter().enumerate().collect::<Vec<_>>();
+    sorted.sort_by_key(|(_, (validator, _))| *validator);
+
+    let sigs = sorted.iter().map(|(_, (_, sig))| sig.clone()).collect::<Vec<_>>();
+    let signature = S::aggregate(&sigs).map_err(|AggregateError::InvalidSignature(i)| {
+      AggregateError::InvalidSignature(sorted[i].0)
+    })?;
+
+    Ok(Commit {
+      end_time,
+      validators: sorted.iter().map(|(_, (validator, _))| *validator).collect(),
+      signature,
+    })
+  }
+
+  /// Merge two commits for the same block, producing one covering the union of their validators.
+  /// Returns None if the commits are from distinct rounds, if the signature scheme can't split
+  /// aggregate signatures, or if the merged signatures fail to aggregate.
+  pub fn merge(&self, other: &Commit<S>) -> Option<Commit<S>> {
+    if self.end_time != other.end_time {
+      return None;
+    }
+
+    let mut precommits =
+      self.validators.iter().copied().zip(S::split_aggregate(&self.signature)?).collect::<Vec<_>>();
+    for (validator, sig) in other.validators.iter().zip(S::split_aggregate(&other.signature)?) {
+      if !self.validators.contains(validator) {
+        precommits.push((*validator, sig));
+      }
+    }
+    Commit::build(self.end_time, &precommits).ok()
+  }
+
+  /// Total weight of the validators participating in this commit.
+  pub fn signed_weight<W: Weights<ValidatorId = S::ValidatorId>>(&self, weights: &W) -> u64 {
+    self.validators.iter().map(|validator| weights.weight(*validator)).sum()
+  }
+
+  /// Fraction of the total weight participating in this commit, enabling requiring more than the
+  /// threshold when assessing a commit's robustness.
+  pub fn signed_fraction<W: Weights<ValidatorId = S::ValidatorId>>(&self, weights: &W) -> f64 {
+    self.signed_weight(weights) as f64 / weights.total_weight() as f64
+  }
+}
+
 /// Weights for the validators present.
 pub trait Weights: Send + Sync {
   type ValidatorId: ValidatorId;
@@ -147,6 +218,10 @@ pub trait Weights: Send + Sync {
   }
 
   /// Weighted round robin function.
+  ///
+  /// This MUST be deterministic across all validators, as disagreeing on the proposer will fork
+  /// consensus. Implementations must not depend on any unspecified ordering, such as a HashMap's
+  /// iteration order. `proposer` is provided as a reference implementation.
   fn proposer(&self, block: BlockNumber, round: RoundNumber) -> Self::ValidatorId;
 }
 
@@ -166,18 +241,119 @@ impl<W: Weights> Weights for Arc<W> {
   }
 }
 
+/// A serializable snapshot of the validator set, and their weights, for a specific block.
+///
+/// This enables determining the proposer for a historical block without a live Weights instance,
+/// such as when a light client verifies a commit.
+#[derive(Clone, PartialEq, Eq, Debug, Encode, Decode)]
+pub struct ValidatorSetSnapshot<V: ValidatorId> {
+  /// Validators, with their weights. The round robin orders them by ID, not as listed here.
+  pub validators: Vec<(V, u64)>,
+}
+
+impl<V: ValidatorId> ValidatorSetSnapshot<V> {
+  /// Create a new snapshot from a list of validators and their weights.
+  pub fn new(validators: Vec<(V, u64)>) -> ValidatorSetSnapshot<V> {
+    ValidatorSetSnapshot { validators }
+  }
+}
+
+impl<V: ValidatorId> Weights for ValidatorSetSnapshot<V> {
+  type ValidatorId = V;
+
+  fn total_weight(&self) -> u64 {
+    self.validators.iter().map(|(_, weight)| weight).sum()
+  }
+
+  fn weight(&self, validator: V) -> u64 {
+    self.validators.iter().find(|(v, _)| *v == validator).map(|(_, weight)| *weight).unwrap_or(0)
+  }
+
+  // Panics if the snapshot has no weight, as it was of a validator set which couldn't have had
+  // consensus
+  fn proposer(&self, block: BlockNumber, round: RoundNumber) -> V {
+    proposer(&self.validators, block, round).expect("snapshot of a validator set without weight")
+  }
+}
+
+/// Error when selecting a proposer.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Error)]
+pub enum ProposerError {
+  /// There were no validators to select from.
+  #[error("no validators")]
+  NoValidators,
+  /// The validators had no weight, so none could be selected.
+  #[error("validators have no weight")]
+  NoWeight,
+}
+
+/// Weighted round robin function, selecting each validator for a share of the rounds proportional
+/// to their weight. Validators are ordered by their IDs, so the result doesn't depend on the order
+/// they're provided in, breaking ties between equal weights deterministically. With equal weights,
+/// this is the same computation performed by Serai's live validator set.
+pub fn proposer<V: ValidatorId>(
+  validators: &[(V, u64)],
+  block: BlockNumber,
+  round: RoundNumber,
+) -> Result<V, ProposerError> {
+  if validators.is_empty() {
+    Err(ProposerError::NoValidators)?;
+  }
+  let total_weight = validators.iter().map(|(_, weight)| weight).sum::<u64>();
+  if total_weight == 0 {
+    Err(ProposerError::NoWeight)?;
+  }
+
+  let mut validators = validators.to_vec();
+  validators.sort_by_key(|(validator, _)| *validator);
+
+  let mut position = block.0.wrapping_add(u64::from(round.0)) % total_weight;
+  for (validator, weight) in validators {
+    if position < weight {
+      return Ok(validator);
+    }
+    position -= weight;
+  }
+  unreachable!("position exceeded the total weight")
+}
+
 /// Simplified error enum representing a block's validity.
 #[derive(Clone, Copy, PartialEq, Eq, Debug, Error, Encode, Decode)]
 pub enum BlockError {
-  /// Malformed block which is wholly invalid.
-  #[error("invalid block")]
-  Fatal,
+  /// Malformed block which is wholly invalid, optionally with a network-defined code for why.
+  /// This code is passed along with the slash for proposing it.
+  #[error("invalid block (code {0:?})")]
+  Fatal(Option<u32>),
   /// Valid block by syntax, with semantics which may or may not be valid yet are locally
   /// considered invalid. If a block fails to validate with this, a slash will not be triggered.
   #[error("invalid block under local view")]
   Temporal,
 }
 
+/// Error when aggregating signatures.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Error)]
+pub enum AggregateError {
+  /// The signature at the specified index couldn't be aggregated.
+  #[error("signature {0} couldn't be aggregated")]
+  InvalidSignature(usize),
+}
+
+/// The reason a validator is being slashed, enabling the network to apply proportionate
+/// penalties.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Encode, Decode)]
+pub enum SlashReason {
+  /// Didn't propose when they were the proposer. This may solely be downtime.
+  FailToPropose,
+  /// Sent a message which was invalid, such as one with an invalid signature or a proposal when
+  /// they weren't the proposer.
+  InvalidMessage,
+  /// Proposed a block which was fatally invalid, with the code `validate` returned for why, if
+  /// there was one.
+  InvalidBlock(Option<u32>),
+  /// Sent multiple, conflicting messages.
+  Equivocation,
+}
+
 /// Trait representing a Block.
 pub trait Block: Send + Sync + Clone + PartialEq + Debug + Encode + Decode {
   // Type used to identify blocks. Presumably a cryptographic hash of the block.
@@ -185,6 +361,12 @@ pub trait Block: Send + Sync + Clone + PartialEq + Debug + Encode + Decode {
 
   /// Return the deterministic, unique ID for this block.
   fn id(&self) -> Self::Id;
+
+  /// Return the time this block claims to have been created at, in seconds since the epoch.
+  /// Blocks which don't expose a time won't have it bounds checked.
+  fn time(&self) -> Option<u64> {
+    None
+  }
 }
 
 #[cfg(feature = "substrate")]
@@ -213,6 +395,37 @@ pub trait Network: Send + Sync {
   /// Network latency time in seconds.
   const LATENCY_TIME: u32;
 
+  /// Interval, in seconds, at which the proposer re-broadcasts its proposal until its round ends,
+  /// so validators who missed it due to network churn still receive it. None disables
+  /// re-broadcasting. As the exact same signed message is re-broadcast, this cannot cause
+  /// equivocation.
+  const PROPOSAL_REBROADCAST_INTERVAL: Option<u32> = None;
+
+  /// Maximum amount of seconds a proposed block's time may be ahead of the local clock.
+  /// Proposals further ahead aren't slashed for, instead being retried once within this bound.
+  const MAX_BLOCK_TIME_DRIFT: u32 = 60;
+
+  /// Amount of rounds, per block, in which a proposer may decline to propose per `should_propose`.
+  /// Declining to propose in these rounds isn't slashed. Once these rounds have passed, proposals
+  /// are forced so consensus still makes progress.
+  const OPTIONAL_PROPOSAL_ROUNDS: u32 = 0;
+
+  /// Whether to emit a signed heartbeat every block time, enabling monitoring liveness separately
+  /// from consensus.
+  const HEARTBEATS: bool = false;
+
+  /// Amount of the most recently finalized blocks to remember, enabling detecting re-proposals of
+  /// already finalized blocks.
+  const RECENT_BLOCKS: usize = 64;
+
+  /// Amount of commits which may be queued on the step channel before sending blocks. As only the
+  /// latest commit matters, the machine coalesces any queued when it receives one.
+  const STEP_BUFFER: usize = 16;
+
+  /// Amount of seconds to spend broadcasting any queued messages when the machine shuts down.
+  /// None disables draining the queue, dropping the messages.
+  const SHUTDOWN_DRAIN_TIMEOUT: Option<u32> = None;
+
   /// The block time is defined as the processing time plus three times the latency.
   fn block_time() -> u32 {
     Self::BLOCK_PROCESSING_TIME + (3 * Self::LATENCY_TIME)
@@ -227,13 +440,17 @@ pub trait Network: Send + Sync {
 
   /// Verify a commit for a given block. Intended for use when syncing or when not an active
   /// validator.
+  ///
+  /// This only requires the block's ID, not its body, so a commit may be verified and stored as a
+  /// finality proof before the block itself is fetched.
   #[must_use]
   fn verify_commit(
     &self,
     id: <Self::Block as Block>::Id,
     commit: &Commit<Self::SignatureScheme>,
   ) -> bool {
-    if commit.validators.iter().collect::<HashSet<_>>().len() != commit.validators.len() {
+    // Validators must be distinct and sorted, so a commit has a single canonical encoding
+    if !commit.validators.windows(2).all(|pair| pair[0] < pair[1]) {
       return false;
     }
 
@@ -246,7 +463,7 @@ pub trait Network: Send + Sync {
     }
 
     let weights = self.weights();
-    commit.validators.iter().map(|v| weights.weight(*v)).sum::<u64>() >= weights.threshold()
+    commit.signed_weight(&weights) >= weights.threshold()
   }
 
   /// Broadcast a message to the other validators. If authenticated channels have already been
@@ -255,9 +472,87 @@ pub trait Network: Send + Sync {
   /// inefficiency while downgrading channels may have wider implications.
   async fn broadcast(&mut self, msg: SignedMessageFor<Self>);
 
-  /// Trigger a slash for the validator in question who was definitively malicious.
-  /// The exact process of triggering a slash is undefined and left to the network as a whole.
-  async fn slash(&mut self, validator: Self::ValidatorId);
+  /// Broadcast a heartbeat. This must not be delivered to other machines as a consensus message.
+  async fn broadcast_heartbeat(&mut self, _heartbeat: HeartbeatFor<Self>) {}
+
+  /// Trigger a slash for the validator in question who was definitively malicious, for the
+  /// specified reason.
+  /// The exact process of triggering a slash, including its severity, is undefined and left to
+  /// the network as a whole.
+  async fn slash(&mut self, validator: Self::ValidatorId, reason: SlashReason);
+
+  /// Called when the machine moves to the next round due to the precommit timeout expiring, as
+  /// opposed to any other reason. Repeated occurrences indicate a persistent split among the
+  /// validators, making this useful for liveness diagnostics.
+  fn precommit_timeout(&mut self, _block: BlockNumber, _round: RoundNumber) {}
+
+  /// Called when moving past a block, for each validator who precommitted in one of its rounds
+  /// without prevoting in it. This isn't provably malicious, as the prevote may simply not have
+  /// been received, yet may indicate a compromised validator and is useful for monitoring.
+  fn precommit_without_prevote(
+    &mut self,
+    _validator: Self::ValidatorId,
+    _block: BlockNumber,
+    _round: RoundNumber,
+  ) {
+  }
+
+  /// Called with every signed message received for a block, in order, once the machine moves past
+  /// it. This log can be replayed via `TendermintMachine::import_log` to reproduce the machine's
+  /// state, such as when debugging a fork.
+  #[cfg(feature = "export")]
+  fn export_log(&mut self, _block: BlockNumber, _log: Vec<SignedMessageFor<Self>>) {}
+
+  /// Whether we should propose a block for the specified block number now, such as if there's
+  /// transactions to include. Only consulted for the first `OPTIONAL_PROPOSAL_ROUNDS` rounds.
+  fn should_propose(&mut self, _block: BlockNumber) -> bool {
+    true
+  }
+
+  /// Build the proposal for the specified block and round, replacing the one returned by
+  /// `add_block`. This is bounded by a deadline of one latency before the propose timeout, so the
+  /// proposal still reaches the other validators in time. If it isn't built by then, or None is
+  /// returned, the proposal returned by `add_block` is proposed instead. Not consulted when
+  /// re-proposing a block which was already found valid.
+  async fn propose(&mut self, _block: BlockNumber, _round: RoundNumber) -> Option<Self::Block> {
+    None
+  }
+
+  /// Whether enough validators are believed to be online for a quorum to be reachable, such as per
+  /// their heartbeats. Consulted at the start of every round.
+  fn quorum_reachable(&self) -> bool {
+    true
+  }
+
+  /// Called when a round starts while a quorum isn't reachable. The machine won't propose into
+  /// such a round, nor slash its proposer for not proposing, yet rounds still time out as usual so
+  /// consensus resumes once a quorum is reachable again.
+  fn on_stall(&mut self, _block: BlockNumber, _round: RoundNumber) {}
+
+  /// The last round the machine will advance to upon a round's precommit timeout expiring.
+  /// Instead of advancing past it, the machine stalls, reporting so via `on_stall`, until a commit
+  /// for the block is received over the step channel. Defaults to the last representable round.
+  fn max_round(&self) -> RoundNumber {
+    RoundNumber(u32::MAX)
+  }
+
+  /// Whether block production is currently halted, such as by governance or a circuit-breaker.
+  /// While halted, the machine doesn't propose and only votes nil, so no blocks are finalized, yet
+  /// it keeps running so consensus resumes once this clears.
+  fn is_halted(&self) -> bool {
+    false
+  }
+
+  /// Persist the commit for a finalized block, such as for later serving as a justification.
+  /// This is called before `add_block`, and is distinct from it so commit persistence is
+  /// decoupled from applying the block.
+  async fn store_commit(
+    &mut self,
+    _number: BlockNumber,
+    _id: <Self::Block as Block>::Id,
+    _commit: &Commit<Self::SignatureScheme>,
+  ) {
+  }
 
   /// Validate a block.
   async fn validate(&mut self, block: &Self::Block) -> Result<(), BlockError>;
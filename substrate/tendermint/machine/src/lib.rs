@@ -2,8 +2,8 @@ use core::fmt::Debug;
 
 use std::{
   sync::Arc,
-  time::{SystemTime, Instant, Duration},
-  collections::VecDeque,
+  time::{UNIX_EPOCH, SystemTime, Instant, Duration},
+  collections::{VecDeque, BTreeSet},
 };
 
 use log::debug;
@@ -15,7 +15,10 @@ use futures::{
   future::{self, Fuse},
   channel::mpsc,
 };
-use tokio::time::sleep;
+use tokio::{
+  sync::watch,
+  time::{sleep, timeout_at},
+};
 
 mod time;
 use time::{sys_time, CanonicalInstant};
@@ -27,6 +30,9 @@ use block::BlockData;
 
 pub(crate) mod message_log;
 
+mod recent;
+pub use recent::RecentBlocks;
+
 /// Traits and types of the external network being integrated with to provide consensus over.
 pub mod ext;
 use ext::*;
@@ -35,8 +41,9 @@ pub(crate) fn commit_msg(end_time: u64, id: &[u8]) -> Vec<u8> {
   [&end_time.to_le_bytes(), id].concat().to_vec()
 }
 
+/// A step of a Tendermint round.
 #[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Encode, Decode)]
-enum Step {
+pub enum Step {
   Propose,
   Prevote,
   Precommit,
@@ -96,6 +103,12 @@ impl<V: ValidatorId, B: Block, S: Signature> SignedMessage<V, B, S> {
     self.msg.block
   }
 
+  /// Validator who claims to have sent this message. This is only authenticated once the
+  /// signature has been verified.
+  pub fn sender(&self) -> V {
+    self.msg.sender
+  }
+
   #[must_use]
   pub fn verify_signature<Scheme: SignatureScheme<ValidatorId = V, Signature = S>>(
     &self,
@@ -105,10 +118,81 @@ impl<V: ValidatorId, B: Block, S: Signature> SignedMessage<V, B, S> {
   }
 }
 
+/// A signed heartbeat, attesting a validator is online regardless of whether consensus is making
+/// progress. Heartbeats are never used by consensus itself.
+#[derive(Clone, PartialEq, Debug, Encode, Decode)]
+pub struct Heartbeat<V: ValidatorId, S: Signature> {
+  /// Validator who sent this heartbeat.
+  pub sender: V,
+  /// Number of the block the sender is currently working on.
+  pub number: BlockNumber,
+  /// Time this heartbeat was created at, in seconds since the epoch.
+  pub timestamp: u64,
+  /// Signature by the sender.
+  pub sig: S,
+}
+
+impl<V: ValidatorId, S: Signature> Heartbeat<V, S> {
+  // Domain separated so a heartbeat's signature can never be confused with a vote's
+  fn msg(sender: V, number: BlockNumber, timestamp: u64) -> Vec<u8> {
+    [b"Tendermint Heartbeat".as_ref(), &(sender, number, timestamp).encode()].concat()
+  }
+
+  /// Create a heartbeat for the specified block. Returns None if the signer isn't a validator.
+  pub async fn new<Sig: Signer<ValidatorId = V, Signature = S>>(
+    signer: &Sig,
+    number: BlockNumber,
+    timestamp: u64,
+  ) -> Option<Self> {
+    let sender = signer.validator_id().await?;
+    let sig = signer.sign(&Self::msg(sender, number, timestamp)).await;
+    Some(Heartbeat { sender, number, timestamp, sig })
+  }
+
+  #[must_use]
+  pub fn verify<Scheme: SignatureScheme<ValidatorId = V, Signature = S>>(
+    &self,
+    signer: &Scheme,
+  ) -> bool {
+    signer.verify(self.sender, &Self::msg(self.sender, self.number, self.timestamp), &self.sig)
+  }
+}
+
+// Reason a message was rejected, logged with the message in question
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum RejectReason {
+  WrongHeight,
+  FutureRoundNotReady,
+  FutureBlockTime,
+  NonProposerProposal,
+  InvalidValidRound,
+  InvalidBlockTime,
+  InvalidBlock(Option<u32>),
+  Equivocation,
+  BadPrecommitSig,
+}
+
+impl RejectReason {
+  // The reason to slash for, if this rejection warrants one
+  fn slash_reason(self) -> Option<SlashReason> {
+    match self {
+      RejectReason::WrongHeight |
+      RejectReason::FutureRoundNotReady |
+      RejectReason::FutureBlockTime => None,
+      RejectReason::NonProposerProposal |
+      RejectReason::InvalidValidRound |
+      RejectReason::BadPrecommitSig => Some(SlashReason::InvalidMessage),
+      RejectReason::InvalidBlockTime => Some(SlashReason::InvalidBlock(None)),
+      RejectReason::InvalidBlock(code) => Some(SlashReason::InvalidBlock(code)),
+      RejectReason::Equivocation => Some(SlashReason::Equivocation),
+    }
+  }
+}
+
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
 enum TendermintError<V: ValidatorId> {
-  Malicious(V),
-  Temporal,
+  Malicious(V, RejectReason),
+  Temporal(RejectReason),
 }
 
 // Type aliases to abstract over generic hell
@@ -126,6 +210,33 @@ pub type SignedMessageFor<N> = SignedMessage<
   <<N as Network>::SignatureScheme as SignatureScheme>::Signature,
 >;
 
+/// Type alias to the Heartbeat type for a given Network
+pub type HeartbeatFor<N> = Heartbeat<
+  <N as Network>::ValidatorId,
+  <<N as Network>::SignatureScheme as SignatureScheme>::Signature,
+>;
+
+/// The state consensus was in when a machine shut down, for post-mortems and to seed a restart
+/// via `TendermintMachine::resume`.
+#[derive(Clone, PartialEq, Debug)]
+pub struct ShutdownReport<V: ValidatorId, B: Block> {
+  /// The block being voted on.
+  pub block: BlockNumber,
+  /// The round the machine was in.
+  pub round: RoundNumber,
+  /// The step the machine was in within that round.
+  pub step: Step,
+  /// The validators messages were received from for this block, in order.
+  pub heard_from: Vec<V>,
+  /// The round and ID of the block the machine was locked on, if any.
+  pub locked: Option<(RoundNumber, B::Id)>,
+  /// The round and block the machine considered valid, if any.
+  pub valid: Option<(RoundNumber, B)>,
+}
+
+/// Type alias to the ShutdownReport type for a given Network
+pub type ShutdownReportFor<N> = ShutdownReport<<N as Network>::ValidatorId, <N as Network>::Block>;
+
 /// A machine executing the Tendermint protocol.
 pub struct TendermintMachine<N: Network> {
   network: N,
@@ -136,12 +247,23 @@ pub struct TendermintMachine<N: Network> {
   queue: VecDeque<MessageFor<N>>,
   msg_recv: mpsc::UnboundedReceiver<SignedMessageFor<N>>,
   #[allow(clippy::type_complexity)]
-  step_recv: mpsc::UnboundedReceiver<(BlockNumber, Commit<N::SignatureScheme>, Option<N::Block>)>,
+  step_recv: mpsc::Receiver<(BlockNumber, Commit<N::SignatureScheme>, Option<N::Block>)>,
+  signer_recv: mpsc::UnboundedReceiver<<N::SignatureScheme as SignatureScheme>::Signer>,
+  // Signer to use once we move to the next block
+  next_signer: Option<<N::SignatureScheme as SignatureScheme>::Signer>,
+  next_proposer: watch::Sender<bool>,
 
   block: BlockData<N>,
+  // The blocks most recently finalized by this machine
+  recent: RecentBlocks<<N::Block as Block>::Id>,
+
+  // When to next emit a heartbeat
+  next_heartbeat: Instant,
 }
 
-pub type StepSender<N> = mpsc::UnboundedSender<(
+/// Sender for the step channel, which is bounded per `Network::STEP_BUFFER`. Only the latest
+/// commit sent is acted on, with any others queued alongside it dropped.
+pub type StepSender<N> = mpsc::Sender<(
   BlockNumber,
   Commit<<N as Network>::SignatureScheme>,
   Option<<N as Network>::Block>,
@@ -149,13 +271,24 @@ pub type StepSender<N> = mpsc::UnboundedSender<(
 
 pub type MessageSender<N> = mpsc::UnboundedSender<SignedMessageFor<N>>;
 
+pub type SignerSender<N> =
+  mpsc::UnboundedSender<<<N as Network>::SignatureScheme as SignatureScheme>::Signer>;
+
 /// A Tendermint machine and its channel to receive messages from the gossip layer over.
 pub struct TendermintHandle<N: Network> {
   /// Channel to trigger the machine to move to the next block.
-  /// Takes in the the previous block's commit, along with the new proposal.
+  /// Takes in the the previous block's commit, along with the new proposal. The commit may be for
+  /// a later block than the machine is on, in which case the machine jumps to the block after it.
   pub step: StepSender<N>,
   /// Channel to send messages received from the P2P layer.
   pub messages: MessageSender<N>,
+  /// Channel to replace the machine's signer, as when rotating keys. The new signer only takes
+  /// effect once the machine moves to the next block, so no block is ever signed for with
+  /// multiple keys. Its key must be present in the signature scheme by then.
+  pub signer: SignerSender<N>,
+  /// Whether we're the proposer for the round after the current one, updated as rounds start. This
+  /// enables preparing a block ahead of time.
+  pub next_proposer: watch::Receiver<bool>,
   /// Tendermint machine to be run on an asynchronous task.
   pub machine: TendermintMachine<N>,
 }
@@ -170,15 +303,67 @@ impl<N: Network + 'static> TendermintMachine<N> {
       // Push it on to the queue. This is done so we only handle one message at a time, and so we
       // can handle our own message before broadcasting it. That way, we fail before before
       // becoming malicious
+      // This also means handling a message never recurses into handling the messages it causes us
+      // to broadcast, however long the cascade
       self.queue.push_back(msg);
     }
   }
 
   // Start a new round. Returns true if we were the proposer
-  fn round(&mut self, round: RoundNumber, time: Option<CanonicalInstant>) -> bool {
-    if let Some(data) =
-      self.block.new_round(round, self.weights.proposer(self.block.number, round), time)
-    {
+  async fn round(&mut self, round: RoundNumber, time: Option<CanonicalInstant>) -> bool {
+    let proposal =
+      self.block.new_round(round, self.weights.proposer(self.block.number, round), time);
+    // There's no next round to propose for if this is the maximum round
+    let max_round = self.network.max_round();
+    let next_proposer = round
+      .next()
+      .filter(|next| next.0 <= max_round.0)
+      .map(|next| self.weights.proposer(self.block.number, next));
+    self
+      .next_proposer
+      .send_replace(next_proposer.is_some() && (next_proposer == self.block.validator_id));
+
+    // Don't propose into a round which can't reach consensus, reporting the stall instead
+    if !self.network.quorum_reachable() {
+      debug!(target: "tendermint", "Quorum isn't reachable, stalling round {}", round.0);
+      self.block.round_mut().stalled = true;
+      self.block.round_mut().set_timeout(Step::Propose);
+      self.network.on_stall(self.block.number, round);
+      return false;
+    }
+
+    if let Some(mut data) = proposal {
+      // Don't propose while halted, letting the round time out to prevoting nil
+      if self.network.is_halted() {
+        debug!(target: "tendermint", "Halted, not proposing for round {}", round.0);
+        self.block.round_mut().set_timeout(Step::Propose);
+        return false;
+      }
+
+      // Decline to propose if the network doesn't want a block yet, and it's still optional
+      // A block which was already found valid is always re-proposed
+      // Set the propose timeout as any other validator would, so we move onto prevoting nil
+      if (round.0 < N::OPTIONAL_PROPOSAL_ROUNDS) &&
+        self.block.valid.is_none() &&
+        (!self.network.should_propose(self.block.number))
+      {
+        debug!(target: "tendermint", "Declining to propose for round {}", round.0);
+        self.block.round_mut().set_timeout(Step::Propose);
+        return false;
+      }
+
+      // Build a fresh proposal, falling back to the one we already have if it isn't built in time
+      if let Data::Proposal(None, block) = &mut data {
+        let deadline = tokio::time::Instant::from_std(self.block.round().proposal_deadline());
+        match timeout_at(deadline, self.network.propose(self.block.number, round)).await {
+          Ok(Some(proposal)) => *block = proposal,
+          Ok(None) => {}
+          Err(_) => {
+            debug!(target: "tendermint", "Proposal for round {} wasn't built in time", round.0);
+          }
+        }
+      }
+
       self.broadcast(data);
       true
     } else {
@@ -187,27 +372,62 @@ impl<N: Network + 'static> TendermintMachine<N> {
   }
 
   // 53-54
-  async fn reset(&mut self, end_round: RoundNumber, proposal: Option<N::Block>) {
+  async fn reset(
+    &mut self,
+    end_round: RoundNumber,
+    prior_time: Option<u64>,
+    proposal: Option<N::Block>,
+  ) {
     // Ensure we have the end time data for the last round
     self.block.populate_end_time(end_round);
-
-    // Sleep until this round ends
     let round_end = self.block.end_time[&end_round];
-    sleep(round_end.instant().saturating_duration_since(Instant::now())).await;
+    self.start_block(BlockNumber(self.block.number.0 + 1), round_end, prior_time, proposal).await;
+  }
+
+  // Move to the specified block, whose first round starts at the specified time
+  async fn start_block(
+    &mut self,
+    number: BlockNumber,
+    start_time: CanonicalInstant,
+    prior_time: Option<u64>,
+    proposal: Option<N::Block>,
+  ) {
+    // Sleep until the prior block's round ends
+    sleep(start_time.instant().saturating_duration_since(Instant::now())).await;
 
     // Clear our outbound message queue
     self.queue = VecDeque::new();
 
+    #[cfg(feature = "export")]
+    self.network.export_log(self.block.number, self.block.log.export());
+
+    // Report any validators who precommitted in a round without prevoting in it
+    for (round, msgs) in &self.block.log.log {
+      for (validator, msgs) in msgs {
+        if msgs.contains_key(&Step::Precommit) && (!msgs.contains_key(&Step::Prevote)) {
+          self.network.precommit_without_prevote(*validator, self.block.number, *round);
+        }
+      }
+    }
+
+    // Now that we've moved past the prior block, switch to our new signer, if one was queued
+    if let Some(signer) = self.next_signer.take() {
+      debug!(target: "tendermint", "Rotating signer for block {}", number.0);
+      self.signer = signer;
+      self.validators = self.network.signature_scheme();
+    }
+
     // Create the new block
     self.block = BlockData::new(
       self.weights.clone(),
-      BlockNumber(self.block.number.0 + 1),
+      number,
       self.signer.validator_id().await,
+      prior_time,
       proposal,
     );
 
     // Start the first round
-    self.round(RoundNumber(0), Some(round_end));
+    self.round(RoundNumber(0), Some(start_time)).await;
   }
 
   async fn reset_by_commit(
@@ -218,7 +438,7 @@ impl<N: Network + 'static> TendermintMachine<N> {
     let mut round = self.block.round().number;
     // If this commit is for a round we don't have, jump up to it
     while self.block.end_time[&round].canonical() < commit.end_time {
-      round.0 += 1;
+      round = round.next().expect("commit isn't for this machine's next block");
       self.block.populate_end_time(round);
     }
     // If this commit is for a prior round, find it
@@ -227,17 +447,19 @@ impl<N: Network + 'static> TendermintMachine<N> {
         panic!("commit isn't for this machine's next block");
       }
       round.0 -= 1;
+      // This round may predate the earliest end time we have, such as when we resumed
+      self.block.populate_end_time(round);
     }
     debug_assert_eq!(self.block.end_time[&round].canonical(), commit.end_time);
 
-    self.reset(round, proposal).await;
+    self.reset(round, None, proposal).await;
   }
 
-  async fn slash(&mut self, validator: N::ValidatorId) {
-    if !self.block.slashes.contains(&validator) {
-      debug!(target: "tendermint", "Slashing validator {:?}", validator);
-      self.block.slashes.insert(validator);
-      self.network.slash(validator).await;
+  async fn slash(&mut self, validator: N::ValidatorId, reason: SlashReason) {
+    if !self.block.slashes.contains(&(validator, reason)) {
+      debug!(target: "tendermint", "Slashing validator {:?} for {:?}", validator, reason);
+      self.block.slashes.insert((validator, reason));
+      self.network.slash(validator, reason).await;
     }
   }
 
@@ -252,10 +474,14 @@ impl<N: Network + 'static> TendermintMachine<N> {
     proposal: N::Block,
   ) -> TendermintHandle<N> {
     let (msg_send, msg_recv) = mpsc::unbounded();
-    let (step_send, step_recv) = mpsc::unbounded();
+    let (step_send, step_recv) = mpsc::channel(N::STEP_BUFFER);
+    let (signer_send, signer_recv) = mpsc::unbounded();
+    let (next_proposer_send, next_proposer_recv) = watch::channel(false);
     TendermintHandle {
       step: step_send,
       messages: msg_send,
+      signer: signer_send,
+      next_proposer: next_proposer_recv,
       machine: {
         let sys_time = sys_time(last_time);
         // If the last block hasn't ended yet, sleep until it has
@@ -275,13 +501,20 @@ impl<N: Network + 'static> TendermintMachine<N> {
           queue: VecDeque::new(),
           msg_recv,
           step_recv,
+          signer_recv,
+          next_signer: None,
+          next_proposer: next_proposer_send,
 
           block: BlockData::new(
             weights,
             BlockNumber(last_block.0 + 1),
             validator_id,
+            None,
             Some(proposal),
           ),
+          recent: RecentBlocks::new(N::RECENT_BLOCKS),
+
+          next_heartbeat: Instant::now(),
         };
 
         // The end time of the last block is the start time for this one
@@ -291,13 +524,60 @@ impl<N: Network + 'static> TendermintMachine<N> {
         // after it, without the standard amount of separation (so their times will be
         // equivalent or minimally offset)
         // For callers wishing to avoid this, they should pass (0, GENESIS + N::block_time())
-        machine.round(RoundNumber(0), Some(CanonicalInstant::new(last_time)));
+        machine.round(RoundNumber(0), Some(CanonicalInstant::new(last_time))).await;
         machine
       },
     }
   }
 
-  pub async fn run(mut self) {
+  /// Replace the message log for the current block with one exported via `Network::export_log`,
+  /// reproducing its state. Messages with invalid signatures, or for other blocks, are ignored.
+  /// This should be called before `run`.
+  #[cfg(feature = "export")]
+  pub fn import_log(&mut self, log: Vec<SignedMessageFor<N>>) {
+    let log = log
+      .into_iter()
+      .filter(|msg| (msg.block() == self.block.number) && msg.verify_signature(&self.validators))
+      .collect();
+    self.block.log = message_log::MessageLog::import(self.weights.clone(), log);
+  }
+
+  /// Resume at the specified round of the current block, as when recovering from a crash, instead
+  /// of re-traversing every round prior. The locked and valid values persisted from when the
+  /// machine was last running must also be restored, else the machine may vote against its lock.
+  /// This should be called before `run`.
+  pub async fn resume(
+    &mut self,
+    round: RoundNumber,
+    locked: Option<(RoundNumber, <N::Block as Block>::Id)>,
+    valid: Option<(RoundNumber, N::Block)>,
+  ) {
+    assert!(round.0 != 0, "resuming at the first round, which the machine already starts at");
+    // Discard anything queued for the first round, such as our proposal for it
+    self.queue = VecDeque::new();
+    self.block.locked = locked;
+    self.block.valid = valid;
+    self.block.populate_prior_end_time(round);
+    self.round(round, None).await;
+  }
+
+  /// Whether a block with the specified ID was one of the last `Network::RECENT_BLOCKS` blocks
+  /// finalized by this machine.
+  pub fn recently_finalized(&self, id: &<N::Block as Block>::Id) -> bool {
+    self.recent.contains(id)
+  }
+
+  async fn heartbeat_future(&self) {
+    if N::HEARTBEATS {
+      sleep(self.next_heartbeat.saturating_duration_since(Instant::now())).await;
+    } else {
+      future::pending::<()>().await;
+    }
+  }
+
+  /// Run the machine until the message or step channel is closed, returning a report of the state
+  /// consensus was in.
+  pub async fn run(mut self) -> ShutdownReportFor<N> {
     loop {
       // Also create a future for if the queue has a message
       // Does not pop_front as if another message has higher priority, its future will be handled
@@ -310,12 +590,27 @@ impl<N: Network + 'static> TendermintMachine<N> {
         // Handle a new block occuring externally (an external sync loop)
         // Has the highest priority as it makes all other futures here irrelevant
         msg = self.step_recv.next() => {
-          if let Some((block_number, commit, proposal)) = msg {
+          if let Some(mut msg) = msg {
+            // Only the latest commit matters, so coalesce any others already queued
+            while let Ok(Some(next)) = self.step_recv.try_next() {
+              if next.0 .0 >= msg.0 .0 {
+                msg = next;
+              }
+            }
+
+            let (block_number, commit, proposal) = msg;
             // Commit is for a block we've already moved past
-            if block_number != self.block.number {
+            if block_number.0 < self.block.number.0 {
               continue;
             }
-            self.reset_by_commit(commit, proposal).await;
+            if block_number == self.block.number {
+              self.reset_by_commit(commit, proposal).await;
+            } else {
+              // Commit is for a future block, so jump to the block after it
+              debug!(target: "tendermint", "Jumping to block {}", block_number.0 + 1);
+              let start_time = CanonicalInstant::new(commit.end_time);
+              self.start_block(BlockNumber(block_number.0 + 1), start_time, None, proposal).await;
+            }
             None
           } else {
             break;
@@ -338,15 +633,39 @@ impl<N: Network + 'static> TendermintMachine<N> {
             match step {
               Step::Propose => {
                 // Slash the validator for not proposing when they should've
-                debug!(target: "tendermint", "Validator didn't propose when they should have");
-                self.slash(
-                  self.weights.proposer(self.block.number, self.block.round().number)
-                ).await;
+                // Proposing is optional for the first rounds, so declining to then isn't slashed
+                // Neither is not proposing into a round which stalled
+                // Observers follow consensus without policing it, so they never slash for this
+                // Nor is anyone slashed while block production is halted
+                if self.block.validator_id.is_some() &&
+                  (self.block.round().number.0 >= N::OPTIONAL_PROPOSAL_ROUNDS) &&
+                  (!self.block.round().stalled) &&
+                  (!self.network.is_halted())
+                {
+                  debug!(target: "tendermint", "Validator didn't propose when they should have");
+                  self.slash(
+                    self.weights.proposer(self.block.number, self.block.round().number),
+                    SlashReason::FailToPropose,
+                  ).await;
+                }
                 self.broadcast(Data::Prevote(None));
               },
               Step::Prevote => self.broadcast(Data::Precommit(None)),
               Step::Precommit => {
-                self.round(RoundNumber(self.block.round().number.0 + 1), None);
+                debug!(target: "tendermint", "Precommit timeout expired, moving to the next round");
+                self.network.precommit_timeout(self.block.number, self.block.round().number);
+                let max_round = self.network.max_round();
+                if let Some(next) =
+                  self.block.round().number.next().filter(|next| next.0 <= max_round.0)
+                {
+                  self.round(next, None).await;
+                } else {
+                  // Never wrap back to round 0, nor exceed the maximum round, instead stalling
+                  // until a commit for this block is received over the step channel
+                  debug!(target: "tendermint", "Reached the maximum round, stalling");
+                  self.block.round_mut().stalled = true;
+                  self.network.on_stall(self.block.number, self.block.round().number);
+                }
                 continue;
               }
             }
@@ -354,12 +673,59 @@ impl<N: Network + 'static> TendermintMachine<N> {
           None
         },
 
+        // Re-broadcast our proposal, if it's time to
+        _ = self.block.round().rebroadcast_future().fuse() => {
+          let (_, msg) = self.block.round_mut().rebroadcast.take().unwrap();
+          if let Some(interval) = N::PROPOSAL_REBROADCAST_INTERVAL {
+            self.block.round_mut().rebroadcast =
+              Some((Instant::now() + Duration::from_secs(interval.into()), msg.clone()));
+          }
+          self.network.broadcast(msg).await;
+          None
+        },
+
+        // Retry a proposal whose block was too far in the future, if it's time to
+        _ = self.block.delayed_future().fuse() => {
+          self.block.take_delayed().map(|msg| (false, msg))
+        },
+
+        // Emit a heartbeat, if it's time to
+        _ = self.heartbeat_future().fuse() => {
+          self.next_heartbeat = Instant::now() + Duration::from_secs(N::block_time().into());
+          let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or(Duration::ZERO).as_secs();
+          if let Some(heartbeat) = Heartbeat::new(&self.signer, self.block.number, now).await {
+            self.network.broadcast_heartbeat(heartbeat).await;
+          }
+          None
+        },
+
+        // Queue a new signer, which replaces any prior one queued
+        signer = self.signer_recv.next() => {
+          self.next_signer = signer.or(self.next_signer.take());
+          None
+        },
+
         // Handle any received messages
         msg = self.msg_recv.next() => {
           if let Some(msg) = msg {
+            // Our own messages were already handled when queued, so drop any echoed back to us
+            // before paying for signature verification
+            // Any of ours we haven't logged, such as one sent before resuming at a later round, is
+            // still verified and handled, so we'd detect equivocating against it
+            // Observers have no validator ID, and accordingly never filter
+            if Some(msg.msg.sender) == self.block.validator_id {
+              let logged = self.block.log.get(msg.msg.round, msg.msg.sender, msg.msg.data.step());
+              if (msg.msg.block != self.block.number) || (logged == Some(&msg.msg.data)) {
+                continue;
+              }
+            }
             if !msg.verify_signature(&self.validators) {
               continue;
             }
+            #[cfg(feature = "export")]
+            if msg.msg.block == self.block.number {
+              self.block.log.record(msg.clone());
+            }
             Some((false, msg.msg))
           } else {
             break;
@@ -374,39 +740,131 @@ impl<N: Network + 'static> TendermintMachine<N> {
         match res {
           Ok(None) => (),
           Ok(Some(block)) => {
-            let mut validators = vec![];
-            let mut sigs = vec![];
-            // Get all precommits for this round
-            for (validator, msgs) in &self.block.log.log[&msg.round] {
-              if let Some(Data::Precommit(Some((id, sig)))) = msgs.get(&Step::Precommit) {
-                // If this precommit was for this block, include it
-                if id == &block.id() {
-                  validators.push(*validator);
-                  sigs.push(sig.clone());
+            // Only include the minimal set of precommits needed, keeping the commit compact
+            // The first attempt will always have a quorum as the finalizer already checked for
+            // consensus
+            let mut commit = None;
+            while let Some(quorum) = self.block.log.minimal_quorum(msg.round, &block.id()) {
+              let end_time = self.block.end_time[&msg.round].canonical();
+              match Commit::<N::SignatureScheme>::build(end_time, &quorum) {
+                Ok(built) => {
+                  commit = Some(built);
+                  break;
+                }
+                // Remove the precommit which failed to aggregate, and retry with the rest
+                Err(AggregateError::InvalidSignature(i)) => {
+                  let validator = quorum[i].0;
+                  debug!(target: "tendermint", "Validator's precommit failed to aggregate");
+                  self
+                    .block
+                    .log
+                    .log
+                    .get_mut(&msg.round)
+                    .unwrap()
+                    .get_mut(&validator)
+                    .unwrap()
+                    .remove(&Step::Precommit);
+                  self.slash(validator, SlashReason::InvalidMessage).await;
                 }
               }
             }
+            // If too many precommits failed to aggregate, wait for more
+            if let Some(commit) = commit {
+              debug_assert!(self.network.verify_commit(block.id(), &commit));
+
+              self.network.store_commit(self.block.number, block.id(), &commit).await;
+              self.recent.insert(self.block.number, block.id());
+              let time = block.time();
+              let proposal = self.network.add_block(block, commit).await;
+              self.reset(msg.round, time, proposal).await;
+            }
+          }
+          Err(TendermintError::Malicious(validator, reason)) => {
+            Self::log_rejection(&msg, reason);
+            if let Some(slash) = reason.slash_reason() {
+              self.slash(validator, slash).await;
+            }
+          }
+          Err(TendermintError::Temporal(reason)) => Self::log_rejection(&msg, reason),
+        }
 
-            let commit = Commit {
-              end_time: self.block.end_time[&msg.round].canonical(),
-              validators,
-              signature: N::SignatureScheme::aggregate(&sigs),
-            };
-            debug_assert!(self.network.verify_commit(block.id(), &commit));
+        if broadcast {
+          let sig = self.signer.sign(&msg.encode()).await;
+          let msg = SignedMessage { msg, sig };
+
+          #[cfg(feature = "export")]
+          if msg.msg.block == self.block.number {
+            self.block.log.record(msg.clone());
+          }
 
-            let proposal = self.network.add_block(block, commit).await;
-            self.reset(msg.round, proposal).await;
+          // If this is our proposal, and we're still on its round, schedule re-broadcasting it
+          if let Some(interval) = N::PROPOSAL_REBROADCAST_INTERVAL {
+            if matches!(msg.msg.data, Data::Proposal(..)) &&
+              (msg.msg.block == self.block.number) &&
+              (msg.msg.round == self.block.round().number)
+            {
+              self.block.round_mut().rebroadcast =
+                Some((Instant::now() + Duration::from_secs(interval.into()), msg.clone()));
+            }
           }
-          Err(TendermintError::Malicious(validator)) => self.slash(validator).await,
-          Err(TendermintError::Temporal) => (),
+
+          self.network.broadcast(msg).await;
         }
+      }
+    }
 
-        if broadcast {
+    // The machine is shutting down. If configured to, broadcast the messages we've already decided
+    // to send so they aren't lost, as a dropped precommit may prevent the others from reaching
+    // quorum
+    if let Some(timeout) = N::SHUTDOWN_DRAIN_TIMEOUT {
+      let drain = async {
+        while let Some(msg) = self.queue.pop_front() {
+          // Handle each as the main loop would, so we never broadcast a message we wouldn't have
+          // and so any messages it causes us to send are also queued
+          // A block it finalizes isn't added, as we're shutting down, and will instead be synced
+          if self.message(msg.clone()).await.is_err() {
+            panic!("honest node had invalid behavior");
+          }
           let sig = self.signer.sign(&msg.encode()).await;
           self.network.broadcast(SignedMessage { msg, sig }).await;
         }
+      };
+      if tokio::time::timeout(Duration::from_secs(timeout.into()), drain).await.is_err() {
+        debug!(target: "tendermint", "Timed out draining the message queue on shutdown");
       }
     }
+
+    let heard_from = self
+      .block
+      .log
+      .log
+      .values()
+      .flat_map(|round| round.keys().copied())
+      .filter(|validator| Some(*validator) != self.block.validator_id)
+      .collect::<BTreeSet<_>>();
+    let report = ShutdownReport {
+      block: self.block.number,
+      round: self.block.round().number,
+      step: self.block.round().step,
+      heard_from: heard_from.into_iter().collect(),
+      locked: self.block.locked.take(),
+      valid: self.block.valid.take(),
+    };
+    debug!(target: "tendermint", "Shut down with {:?}", report);
+    report
+  }
+
+  // Log why a message was rejected, under its own target so this may be enabled independently
+  fn log_rejection(msg: &MessageFor<N>, reason: RejectReason) {
+    debug!(
+      target: "tendermint::rejected",
+      "sender={:?} block={} round={} step={:?} reason={:?}",
+      msg.sender,
+      msg.block.0,
+      msg.round.0,
+      msg.data.step(),
+      reason,
+    );
   }
 
   // Returns Ok(true) if this was a Precommit which had its signature validated
@@ -426,7 +884,7 @@ impl<N: Network + 'static> TendermintMachine<N> {
       if let Some(end_time) = self.block.end_time.get(&round) {
         if !self.validators.verify(sender, &commit_msg(end_time.canonical(), id.as_ref()), sig) {
           debug!(target: "tendermint", "Validator produced an invalid commit signature");
-          Err(TendermintError::Malicious(sender))?;
+          Err(TendermintError::Malicious(sender, RejectReason::BadPrecommitSig))?;
         }
         return Ok(true);
       }
@@ -434,12 +892,32 @@ impl<N: Network + 'static> TendermintMachine<N> {
     Ok(false)
   }
 
+  // Returns the proposal for the specified round if it has a supermajority of precommits
+  // 49-52
+  async fn finalized(&self, round: RoundNumber) -> Option<N::Block> {
+    let proposer = self.weights.proposer(self.block.number, round);
+
+    // Get the proposal
+    if let Some(Data::Proposal(_, block)) = self.block.log.get(round, proposer, Step::Propose) {
+      // Check if it has gotten a sufficient amount of precommits
+      // Use a junk signature since message equality disregards the signature
+      if self
+        .block
+        .log
+        .has_consensus(round, Data::Precommit(Some((block.id(), self.signer.sign(&[]).await))))
+      {
+        return Some(block.clone());
+      }
+    }
+    None
+  }
+
   async fn message(
     &mut self,
     msg: MessageFor<N>,
   ) -> Result<Option<N::Block>, TendermintError<N::ValidatorId>> {
     if msg.block != self.block.number {
-      Err(TendermintError::Temporal)?;
+      Err(TendermintError::Temporal(RejectReason::WrongHeight))?;
     }
 
     // If this is a precommit, verify its signature
@@ -450,9 +928,50 @@ impl<N: Network + 'static> TendermintMachine<N> {
       (msg.sender != self.weights.proposer(msg.block, msg.round))
     {
       debug!(target: "tendermint", "Validator who wasn't the proposer proposed");
-      Err(TendermintError::Malicious(msg.sender))?;
+      Err(TendermintError::Malicious(msg.sender, RejectReason::NonProposerProposal))?;
     };
 
+    // A proposal's valid round must be prior to the round it's proposed in
+    // This is structurally invalid regardless of our state, so reject it before it's ever logged
+    // or validated
+    if let Data::Proposal(Some(vr), _) = &msg.data {
+      if vr.0 >= msg.round.0 {
+        debug!(target: "tendermint", "Validator claimed a round from the future was valid");
+        Err(TendermintError::Malicious(msg.sender, RejectReason::InvalidValidRound))?;
+      }
+    }
+
+    // A proposed block's time must not be before the prior block
+    // If it's too far in the future, our clock may simply be behind, so retry it once it's within
+    // bounds
+    if let Data::Proposal(_, block) = &msg.data {
+      if let Some(time) = block.time() {
+        if self.block.prior_time.map(|prior| time < prior).unwrap_or(false) {
+          debug!(target: "tendermint", "Validator proposed a block from before the prior block");
+          Err(TendermintError::Malicious(msg.sender, RejectReason::InvalidBlockTime))?;
+        }
+
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or(Duration::ZERO).as_secs();
+        let latest = now + u64::from(N::MAX_BLOCK_TIME_DRIFT);
+        if time > latest {
+          debug!(target: "tendermint", "Validator proposed a block too far in the future");
+          // Only one proposal is kept per round, and only for rounds we've reached, bounding this
+          // It's only kept if it'd be retried before its round ends, which also bounds the delay
+          // for blocks timestamped absurdly far into the future
+          if msg.round.0 <= self.block.round().number.0 {
+            let end = self.block.end_time.get(&msg.round).map(CanonicalInstant::instant);
+            let retry = Instant::now()
+              .checked_add(Duration::from_secs(time - latest))
+              .filter(|retry| end.map(|end| *retry < end).unwrap_or(false));
+            if let Some(retry) = retry {
+              self.block.delayed.insert(msg.round, (retry, msg.clone()));
+            }
+          }
+          Err(TendermintError::Temporal(RejectReason::FutureBlockTime))?;
+        }
+      }
+    }
+
     if !self.block.log.log(msg.clone())? {
       return Ok(None);
     }
@@ -460,21 +979,11 @@ impl<N: Network + 'static> TendermintMachine<N> {
     // All functions, except for the finalizer and the jump, are locked to the current round
 
     // Run the finalizer to see if it applies
-    // 49-52
+    // Only a proposal or a precommit can complete finalization, and either is checked here as soon
+    // as it's logged, so no other message needs to re-run this
     if matches!(msg.data, Data::Proposal(..)) || matches!(msg.data, Data::Precommit(_)) {
-      let proposer = self.weights.proposer(self.block.number, msg.round);
-
-      // Get the proposal
-      if let Some(Data::Proposal(_, block)) = self.block.log.get(msg.round, proposer, Step::Propose)
-      {
-        // Check if it has gotten a sufficient amount of precommits
-        // Use a junk signature since message equality disregards the signature
-        if self.block.log.has_consensus(
-          msg.round,
-          Data::Precommit(Some((block.id(), self.signer.sign(&[]).await))),
-        ) {
-          return Ok(Some(block.clone()));
-        }
+      if let Some(block) = self.finalized(msg.round).await {
+        return Ok(Some(block));
       }
     }
 
@@ -508,18 +1017,18 @@ impl<N: Network + 'static> TendermintMachine<N> {
                 .get_mut(validator)
                 .unwrap()
                 .remove(&Step::Precommit);
-              self.slash(*validator).await;
+              self.slash(*validator, SlashReason::InvalidMessage).await;
             }
           }
         }
         // If we're the proposer, return now so we re-run processing with our proposal
         // If we continue now, it'd just be wasted ops
-        if self.round(msg.round, None) {
+        if self.round(msg.round, None).await {
           return Ok(None);
         }
       } else {
         // Future round which we aren't ready to jump to, so return for now
-        return Ok(None);
+        Err(TendermintError::Temporal(RejectReason::FutureRoundNotReady))?;
       }
     }
 
@@ -564,13 +1073,14 @@ impl<N: Network + 'static> TendermintMachine<N> {
       let (valid, err) = match self.network.validate(block).await {
         Ok(_) => (true, Ok(None)),
         Err(BlockError::Temporal) => (false, Ok(None)),
-        Err(BlockError::Fatal) => (false, {
+        Err(BlockError::Fatal(code)) => (false, {
           debug!(target: "tendermint", "Validator proposed a fatally invalid block");
-          Err(TendermintError::Malicious(proposer))
+          Err(TendermintError::Malicious(proposer, RejectReason::InvalidBlock(code)))
         }),
       };
       // Create a raw vote which only requires block validity as a basis for the actual vote.
-      let raw_vote = Some(block.id()).filter(|_| valid);
+      // While halted, we always vote nil
+      let raw_vote = Some(block.id()).filter(|_| valid && (!self.network.is_halted()));
 
       // If locked is none, it has a round of -1 according to the protocol. That satisfies
       // 23 and 29. If it's some, both are satisfied if they're for the same ID. If it's some
@@ -580,12 +1090,8 @@ impl<N: Network + 'static> TendermintMachine<N> {
       let mut vote = raw_vote.filter(|_| locked);
 
       if let Some(vr) = vr {
-        // Malformed message
-        if vr.0 >= self.block.round().number.0 {
-          debug!(target: "tendermint", "Validator claimed a round from the future was valid");
-          Err(TendermintError::Malicious(msg.sender))?;
-        }
-
+        // The valid round was already checked to be prior to this round when the proposal was
+        // received
         if self.block.log.has_consensus(*vr, Data::Prevote(Some(block.id()))) {
           // Allow differing locked values if the proposal has a newer valid round
           // This is the other condition described above
@@ -596,6 +1102,16 @@ impl<N: Network + 'static> TendermintMachine<N> {
           self.broadcast(Data::Prevote(vote));
           return err;
         }
+
+        // If the valid round can no longer achieve consensus on this block, prevote nil
+        // This isn't slashed for, as equivocating prevoters may have shown the proposer a quorum
+        // while showing us otherwise, so our log doesn't prove the proposer lied
+        // Else, we simply haven't received enough of that round's messages yet, so wait
+        if !self.block.log.consensus_possible(*vr, Data::Prevote(Some(block.id()))) {
+          debug!(target: "tendermint", "Proposal's valid round can't have had consensus");
+          self.broadcast(Data::Prevote(None));
+          return err;
+        }
       } else {
         self.broadcast(Data::Prevote(vote));
         return err;
@@ -616,13 +1132,16 @@ impl<N: Network + 'static> TendermintMachine<N> {
       // The run once condition is implemented above. Since valid will always be set by this, it
       // not being set, or only being set historically, means this has yet to be run
 
-      if self.block.log.has_consensus(self.block.round().number, Data::Prevote(Some(block.id()))) {
+      // While halted, this is deferred, so we neither lock onto nor precommit the block
+      if (!self.network.is_halted()) &&
+        self.block.log.has_consensus(self.block.round().number, Data::Prevote(Some(block.id())))
+      {
         match self.network.validate(block).await {
           Ok(_) => (),
           Err(BlockError::Temporal) => (),
-          Err(BlockError::Fatal) => {
+          Err(BlockError::Fatal(code)) => {
             debug!(target: "tendermint", "Validator proposed a fatally invalid block");
-            Err(TendermintError::Malicious(proposer))?
+            Err(TendermintError::Malicious(proposer, RejectReason::InvalidBlock(code)))?
           }
         };
 
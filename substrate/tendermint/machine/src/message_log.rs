@@ -1,19 +1,57 @@
+use core::cmp::Reverse;
 use std::{sync::Arc, collections::HashMap};
 
 use log::debug;
 
-use crate::{ext::*, RoundNumber, Step, Data, DataFor, MessageFor, TendermintError};
+use crate::{ext::*, RoundNumber, Step, Data, DataFor, MessageFor, TendermintError, RejectReason};
+#[cfg(feature = "export")]
+use crate::SignedMessageFor;
 
 type RoundLog<N> = HashMap<<N as Network>::ValidatorId, HashMap<Step, DataFor<N>>>;
 pub(crate) struct MessageLog<N: Network> {
   weights: Arc<N::Weights>,
-  precommitted: HashMap<N::ValidatorId, <N::Block as Block>::Id>,
+  // The block each validator precommitted to, and the latest round they did so in
+  precommitted: HashMap<N::ValidatorId, (RoundNumber, <N::Block as Block>::Id)>,
   pub(crate) log: HashMap<RoundNumber, RoundLog<N>>,
+  // Every signed message received for this block, in order, enabling exporting the log
+  #[cfg(feature = "export")]
+  signed: Vec<SignedMessageFor<N>>,
 }
 
 impl<N: Network> MessageLog<N> {
   pub(crate) fn new(weights: Arc<N::Weights>) -> MessageLog<N> {
-    MessageLog { weights, precommitted: HashMap::new(), log: HashMap::new() }
+    MessageLog {
+      weights,
+      precommitted: HashMap::new(),
+      log: HashMap::new(),
+      #[cfg(feature = "export")]
+      signed: vec![],
+    }
+  }
+
+  // Reconstruct a log by replaying an exported one
+  // Messages which error are still retained, so re-exporting is lossless
+  #[cfg(feature = "export")]
+  pub(crate) fn import(weights: Arc<N::Weights>, msgs: Vec<SignedMessageFor<N>>) -> MessageLog<N> {
+    let mut log = MessageLog::new(weights);
+    for msg in msgs {
+      let _ = log.log(msg.msg.clone());
+      log.record(msg);
+    }
+    log
+  }
+
+  // Record a signed message, so it's included when exporting
+  #[cfg(feature = "export")]
+  pub(crate) fn record(&mut self, msg: SignedMessageFor<N>) {
+    if !self.signed.contains(&msg) {
+      self.signed.push(msg);
+    }
+  }
+
+  #[cfg(feature = "export")]
+  pub(crate) fn export(&self) -> Vec<SignedMessageFor<N>> {
+    self.signed.clone()
   }
 
   // Returns true if it's a new message
@@ -32,20 +70,32 @@ impl<N: Network> MessageLog<N> {
           target: "tendermint",
           "Validator sent multiple messages for the same block + round + step"
         );
-        Err(TendermintError::Malicious(msg.sender))?;
+        Err(TendermintError::Malicious(msg.sender, RejectReason::Equivocation))?;
       }
       return Ok(false);
     }
 
-    // If they already precommitted to a distinct hash, error
+    // If they already precommitted to a distinct hash, in any round, error
+    // Precommits to the same hash in prior rounds remain valid, as honest validators will produce
+    // them if locked, and they may simply arrive out of order. The latest round they precommitted
+    // in is kept, so a stale precommit never replaces a newer one
     if let Data::Precommit(Some((hash, _))) = &msg.data {
-      if let Some(prev) = self.precommitted.get(&msg.sender) {
+      let mut latest = msg.round;
+      if let Some((prev_round, prev)) = self.precommitted.get(&msg.sender) {
         if hash != prev {
-          debug!(target: "tendermint", "Validator precommitted to multiple blocks");
-          Err(TendermintError::Malicious(msg.sender))?;
+          debug!(
+            target: "tendermint",
+            "Validator precommitted to multiple blocks, in rounds {} and {}",
+            prev_round.0,
+            msg.round.0,
+          );
+          Err(TendermintError::Malicious(msg.sender, RejectReason::Equivocation))?;
+        }
+        if prev_round.0 > latest.0 {
+          latest = *prev_round;
         }
       }
-      self.precommitted.insert(msg.sender, *hash);
+      self.precommitted.insert(msg.sender, (latest, *hash));
     }
 
     msgs.insert(step, msg.data);
@@ -57,7 +107,7 @@ impl<N: Network> MessageLog<N> {
   pub(crate) fn message_instances(&self, round: RoundNumber, data: DataFor<N>) -> (u64, u64) {
     let mut participating = 0;
     let mut weight = 0;
-    for (participant, msgs) in &self.log[&round] {
+    for (participant, msgs) in self.log.get(&round).into_iter().flatten() {
       if let Some(msg) = msgs.get(&data.step()) {
         let validator_weight = self.weights.weight(*participant);
         participating += validator_weight;
@@ -97,6 +147,48 @@ impl<N: Network> MessageLog<N> {
     weight >= self.weights.threshold()
   }
 
+  // Check if consensus on a specific piece of data is still possible, assuming all validators who
+  // have yet to send a message for this step will agree with it
+  pub(crate) fn consensus_possible(&self, round: RoundNumber, data: DataFor<N>) -> bool {
+    let (participating, weight) = self.message_instances(round, data);
+    (weight + (self.weights.total_weight() - participating)) >= self.weights.threshold()
+  }
+
+  // Get the minimal set of precommits for the specified block which meets the threshold, selecting
+  // the heaviest validators first. Returns None if the precommits don't meet the threshold
+  #[allow(clippy::type_complexity)]
+  pub(crate) fn minimal_quorum(
+    &self,
+    round: RoundNumber,
+    id: &<N::Block as Block>::Id,
+  ) -> Option<Vec<(N::ValidatorId, <N::SignatureScheme as SignatureScheme>::Signature)>> {
+    let mut precommits = vec![];
+    for (validator, msgs) in self.log.get(&round)? {
+      if let Some(Data::Precommit(Some((precommit_id, sig)))) = msgs.get(&Step::Precommit) {
+        if precommit_id == id {
+          precommits.push((*validator, sig.clone()));
+        }
+      }
+    }
+    // Break ties between equally weighted validators by their ID, so the quorum selected doesn't
+    // depend on the order the precommits were received in
+    precommits.sort_by_key(|(validator, _)| (Reverse(self.weights.weight(*validator)), *validator));
+
+    let threshold = self.weights.threshold();
+    let mut weight = 0;
+    let mut quorum = vec![];
+    for (validator, sig) in precommits {
+      if weight >= threshold {
+        break;
+      }
+      weight += self.weights.weight(validator);
+      quorum.push((validator, sig));
+    }
+    // Commits list their validators in ascending order
+    quorum.sort_by_key(|(validator, _)| *validator);
+    Some(quorum).filter(|_| weight >= threshold)
+  }
+
   pub(crate) fn get(
     &self,
     round: RoundNumber,
@@ -0,0 +1,38 @@
+use std::collections::VecDeque;
+
+use crate::ext::BlockNumber;
+
+/// A bounded cache of the most recently finalized blocks, evicting the oldest once full.
+#[derive(Clone, Debug)]
+pub struct RecentBlocks<Id: Copy + PartialEq> {
+  capacity: usize,
+  blocks: VecDeque<(BlockNumber, Id)>,
+}
+
+impl<Id: Copy + PartialEq> RecentBlocks<Id> {
+  /// Create a cache retaining the specified amount of blocks.
+  pub fn new(capacity: usize) -> RecentBlocks<Id> {
+    RecentBlocks { capacity, blocks: VecDeque::with_capacity(capacity) }
+  }
+
+  /// Record a finalized block, evicting the oldest block if the cache is full.
+  pub fn insert(&mut self, number: BlockNumber, id: Id) {
+    if self.capacity == 0 {
+      return;
+    }
+    if self.blocks.len() == self.capacity {
+      self.blocks.pop_front();
+    }
+    self.blocks.push_back((number, id));
+  }
+
+  /// The number of the block with the specified ID, if it was recently finalized.
+  pub fn get(&self, id: &Id) -> Option<BlockNumber> {
+    self.blocks.iter().find(|(_, block)| block == id).map(|(number, _)| *number)
+  }
+
+  /// Whether a block with the specified ID was recently finalized.
+  pub fn contains(&self, id: &Id) -> bool {
+    self.get(id).is_some()
+  }
+}
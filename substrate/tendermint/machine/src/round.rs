@@ -11,6 +11,7 @@ use crate::{
   time::CanonicalInstant,
   Step,
   ext::{RoundNumber, Network},
+  SignedMessageFor,
 };
 
 pub(crate) struct RoundData<N: Network> {
@@ -19,6 +20,10 @@ pub(crate) struct RoundData<N: Network> {
   pub(crate) start_time: CanonicalInstant,
   pub(crate) step: Step,
   pub(crate) timeouts: HashMap<Step, Instant>,
+  // Our proposal for this round, if we're the proposer, and when to next re-broadcast it
+  pub(crate) rebroadcast: Option<(Instant, SignedMessageFor<N>)>,
+  // Whether a quorum was unreachable when this round started
+  pub(crate) stalled: bool,
 }
 
 impl<N: Network> RoundData<N> {
@@ -29,27 +34,58 @@ impl<N: Network> RoundData<N> {
       start_time,
       step: Step::Propose,
       timeouts: HashMap::new(),
+      rebroadcast: None,
+      stalled: false,
     }
   }
 
+  // How long after the start of the specified round the specified step times out
+  fn offset(number: RoundNumber, step: Step) -> Duration {
+    // Performed over u64s, saturating, so late rounds don't overflow
+    let rounds = u64::from(number.0) + 1;
+    let adjusted_block = u64::from(N::BLOCK_PROCESSING_TIME).saturating_mul(rounds);
+    let adjusted_latency = u64::from(N::LATENCY_TIME).saturating_mul(rounds);
+    Duration::from_secs(adjusted_block.saturating_add(match step {
+      Step::Propose => adjusted_latency,
+      Step::Prevote => adjusted_latency.saturating_mul(2),
+      Step::Precommit => adjusted_latency.saturating_mul(3),
+    }))
+  }
+
   fn timeout(&self, step: Step) -> CanonicalInstant {
-    let adjusted_block = N::BLOCK_PROCESSING_TIME * (self.number.0 + 1);
-    let adjusted_latency = N::LATENCY_TIME * (self.number.0 + 1);
-    let offset = Duration::from_secs(
-      (match step {
-        Step::Propose => adjusted_block + adjusted_latency,
-        Step::Prevote => adjusted_block + (2 * adjusted_latency),
-        Step::Precommit => adjusted_block + (3 * adjusted_latency),
-      })
-      .into(),
-    );
-    self.start_time + offset
+    self.start_time + Self::offset(self.number, step)
   }
 
   pub(crate) fn end_time(&self) -> CanonicalInstant {
     self.timeout(Step::Precommit)
   }
 
+  // The end time of the specified round, calculated directly from the start time of the first
+  // round instead of from the end time of every round prior
+  pub(crate) fn end_time_since_first(
+    first_start_time: CanonicalInstant,
+    number: RoundNumber,
+  ) -> CanonicalInstant {
+    // Each round is one round's worth of time longer than the round prior
+    // Performed over u128s, which can't overflow, then saturated into a u64
+    let rounds = u128::from(number.0) + 1;
+    let round_time = u128::from(N::BLOCK_PROCESSING_TIME) + (3 * u128::from(N::LATENCY_TIME));
+    let secs = round_time * ((rounds * (rounds + 1)) / 2);
+    first_start_time + Duration::from_secs(u64::try_from(secs).unwrap_or(u64::MAX))
+  }
+
+  // The start time of the specified round, which is also the end time of the round prior, derived
+  // from its end time
+  pub(crate) fn start_time(number: RoundNumber, end_time: CanonicalInstant) -> CanonicalInstant {
+    end_time - Self::offset(number, Step::Precommit)
+  }
+
+  // The deadline for building our proposal, one latency before the propose timeout
+  pub(crate) fn proposal_deadline(&self) -> Instant {
+    let timeout = self.timeout(Step::Propose).instant();
+    timeout.checked_sub(Duration::from_secs(N::LATENCY_TIME.into())).unwrap_or(timeout)
+  }
+
   pub(crate) fn set_timeout(&mut self, step: Step) {
     let timeout = self.timeout(step).instant();
     self.timeouts.entry(step).or_insert(timeout);
@@ -80,4 +116,13 @@ impl<N: Network> RoundData<N> {
       step = precommit_timeout => step,
     }
   }
+
+  // Resolves when our proposal should be re-broadcast, never resolving if we have no proposal
+  pub(crate) async fn rebroadcast_future(&self) {
+    if let Some((time, _)) = self.rebroadcast.as_ref() {
+      sleep(time.saturating_duration_since(Instant::now())).await;
+    } else {
+      future::pending::<()>().await;
+    }
+  }
 }
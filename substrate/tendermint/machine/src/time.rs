@@ -1,4 +1,4 @@
-use core::ops::Add;
+use core::ops::{Add, Sub};
 use std::time::{UNIX_EPOCH, SystemTime, Instant, Duration};
 
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
@@ -36,9 +36,27 @@ impl CanonicalInstant {
   }
 }
 
+// The furthest an Instant is moved at once, as Instants can't represent arbitrarily distant times
+// This is over a century, so anything further is treated as never being reached
+const MAX_INSTANT_OFFSET: Duration = Duration::from_secs(1 << 32);
+
+// Both of these saturate, as the times of the latest rounds are unrepresentable
 impl Add<Duration> for CanonicalInstant {
   type Output = CanonicalInstant;
   fn add(self, duration: Duration) -> CanonicalInstant {
-    CanonicalInstant { time: self.time + duration.as_secs(), instant: self.instant + duration }
+    CanonicalInstant {
+      time: self.time.saturating_add(duration.as_secs()),
+      instant: self.instant + duration.min(MAX_INSTANT_OFFSET),
+    }
+  }
+}
+
+impl Sub<Duration> for CanonicalInstant {
+  type Output = CanonicalInstant;
+  fn sub(self, duration: Duration) -> CanonicalInstant {
+    CanonicalInstant {
+      time: self.time.saturating_sub(duration.as_secs()),
+      instant: self.instant.checked_sub(duration.min(MAX_INSTANT_OFFSET)).unwrap_or(self.instant),
+    }
   }
 }
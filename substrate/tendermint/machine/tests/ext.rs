@@ -1,5 +1,8 @@
 use std::{
-  sync::Arc,
+  sync::{
+    Arc, Mutex,
+    atomic::{AtomicBool, AtomicUsize, Ordering},
+  },
   time::{UNIX_EPOCH, SystemTime, Duration},
 };
 
@@ -7,48 +10,72 @@ use async_trait::async_trait;
 
 use parity_scale_codec::{Encode, Decode};
 
-use futures::SinkExt;
+use futures::{SinkExt, StreamExt, channel::mpsc};
 use tokio::{sync::RwLock, time::sleep};
 
 use tendermint_machine::{
-  ext::*, SignedMessageFor, StepSender, MessageSender, TendermintMachine, TendermintHandle,
+  ext::*, SignedMessageFor, Heartbeat, StepSender, MessageSender, SignerSender, TendermintMachine,
+  TendermintHandle, RecentBlocks, Step,
 };
 
 type TestValidatorId = u16;
 type TestBlockId = [u8; 4];
 
-struct TestSigner(u16);
+// A validator and which of their keys this is
+struct TestSigner(u16, u8);
 #[async_trait]
 impl Signer for TestSigner {
   type ValidatorId = TestValidatorId;
   type Signature = [u8; 32];
 
+  // Signers outside of the validator set are observers
   async fn validator_id(&self) -> Option<TestValidatorId> {
-    Some(self.0)
+    Some(self.0).filter(|id| *id < 4)
   }
 
   async fn sign(&self, msg: &[u8]) -> [u8; 32] {
     let mut sig = [0; 32];
     sig[.. 2].copy_from_slice(&self.0.to_le_bytes());
-    sig[2 .. (2 + 30.min(msg.len()))].copy_from_slice(&msg[.. 30.min(msg.len())]);
+    sig[2] = self.1;
+    sig[3 .. (3 + 29.min(msg.len()))].copy_from_slice(&msg[.. 29.min(msg.len())]);
     sig
   }
 }
 
-struct TestSignatureScheme;
+// The amount of verifications performed, shared with the events of the network it's for
+#[derive(Clone, Default, Debug)]
+struct TestSignatureScheme(Arc<AtomicUsize>);
+// Every instance is the same scheme, regardless of the verifications it's performed
+impl PartialEq for TestSignatureScheme {
+  fn eq(&self, _: &Self) -> bool {
+    true
+  }
+}
+impl Eq for TestSignatureScheme {}
+
 impl SignatureScheme for TestSignatureScheme {
   type ValidatorId = TestValidatorId;
   type Signature = [u8; 32];
   type AggregateSignature = Vec<[u8; 32]>;
   type Signer = TestSigner;
 
+  // Any of a validator's keys are accepted, as if every key was already registered for them
   #[must_use]
   fn verify(&self, validator: u16, msg: &[u8], sig: &[u8; 32]) -> bool {
-    (sig[.. 2] == validator.to_le_bytes()) && (sig[2 ..] == [msg, &[0; 30]].concat()[.. 30])
+    self.0.fetch_add(1, Ordering::SeqCst);
+    (sig[.. 2] == validator.to_le_bytes()) && (sig[3 ..] == [msg, &[0; 29]].concat()[.. 29])
   }
 
-  fn aggregate(sigs: &[[u8; 32]]) -> Vec<[u8; 32]> {
-    sigs.to_vec()
+  // Signatures by a validator's last key fail to aggregate, despite verifying
+  fn aggregate(sigs: &[[u8; 32]]) -> Result<Vec<[u8; 32]>, AggregateError> {
+    if let Some(i) = sigs.iter().position(|sig| sig[2] == u8::MAX) {
+      Err(AggregateError::InvalidSignature(i))?;
+    }
+    Ok(sigs.to_vec())
+  }
+
+  fn split_aggregate(sigs: &Vec<[u8; 32]>) -> Option<Vec<[u8; 32]>> {
+    Some(sigs.clone())
   }
 
   #[must_use]
@@ -66,15 +93,26 @@ impl SignatureScheme for TestSignatureScheme {
   }
 }
 
-struct TestWeights;
+// The weight of each validator, and the threshold
+#[derive(Clone, Copy, Debug)]
+struct TestWeights([u64; 4], u64);
+impl Default for TestWeights {
+  fn default() -> Self {
+    TestWeights([1; 4], 3)
+  }
+}
+
 impl Weights for TestWeights {
   type ValidatorId = TestValidatorId;
 
   fn total_weight(&self) -> u64 {
-    4
+    self.0.iter().sum()
   }
   fn weight(&self, id: TestValidatorId) -> u64 {
-    [1; 4][usize::try_from(id).unwrap()]
+    self.0[usize::try_from(id).unwrap()]
+  }
+  fn threshold(&self) -> u64 {
+    self.1
   }
 
   fn proposer(&self, number: BlockNumber, round: RoundNumber) -> TestValidatorId {
@@ -82,22 +120,64 @@ impl Weights for TestWeights {
   }
 }
 
+fn now() -> u64 {
+  SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+}
+
 #[derive(Clone, PartialEq, Debug, Encode, Decode)]
 struct TestBlock {
   id: TestBlockId,
+  time: u64,
   valid: Result<(), BlockError>,
 }
 
+impl TestBlock {
+  fn new(id: u32) -> TestBlock {
+    TestBlock { id: id.to_le_bytes(), time: now(), valid: Ok(()) }
+  }
+}
+
 impl Block for TestBlock {
   type Id = TestBlockId;
 
   fn id(&self) -> TestBlockId {
     self.id
   }
+
+  fn time(&self) -> Option<u64> {
+    Some(self.time)
+  }
+}
+
+// Events observed by the validators, and their configuration, shared across all of them
+#[allow(clippy::type_complexity)]
+#[derive(Default)]
+struct TestEvents {
+  slashes: RwLock<Vec<(TestValidatorId, SlashReason)>>,
+  stored_commits: RwLock<Vec<(TestValidatorId, TestBlockId, Commit<TestSignatureScheme>)>>,
+  added_blocks: RwLock<Vec<(TestValidatorId, TestBlockId, Commit<TestSignatureScheme>)>>,
+  decline_proposals: AtomicBool,
+  quorum_unreachable: AtomicBool,
+  stalls: RwLock<Vec<(TestValidatorId, BlockNumber, RoundNumber)>>,
+  precommit_timeouts: RwLock<Vec<(TestValidatorId, BlockNumber, RoundNumber)>>,
+  slow_proposals: AtomicBool,
+  precommits_without_prevotes: RwLock<Vec<(TestValidatorId, BlockNumber, RoundNumber)>>,
+  halted: AtomicBool,
+  max_round: Option<RoundNumber>,
+  weights: Option<TestWeights>,
+  validations: AtomicUsize,
+  verifications: Arc<AtomicUsize>,
+  #[cfg(feature = "export")]
+  exported_logs: RwLock<Vec<(BlockNumber, Vec<SignedMessageFor<TestNetwork>>)>>,
 }
+type Events = Arc<TestEvents>;
 
 #[allow(clippy::type_complexity)]
-struct TestNetwork(u16, Arc<RwLock<Vec<(MessageSender<Self>, StepSender<Self>)>>>);
+struct TestNetwork(
+  u16,
+  Arc<RwLock<Vec<(MessageSender<Self>, StepSender<Self>, SignerSender<Self>)>>>,
+  Events,
+);
 
 #[async_trait]
 impl Network for TestNetwork {
@@ -109,30 +189,92 @@ impl Network for TestNetwork {
   const BLOCK_PROCESSING_TIME: u32 = 2;
   const LATENCY_TIME: u32 = 1;
 
+  const PROPOSAL_REBROADCAST_INTERVAL: Option<u32> = Some(2);
+  const SHUTDOWN_DRAIN_TIMEOUT: Option<u32> = Some(5);
+  const OPTIONAL_PROPOSAL_ROUNDS: u32 = 8;
+
   fn signer(&self) -> TestSigner {
-    TestSigner(self.0)
+    TestSigner(self.0, 0)
   }
 
   fn signature_scheme(&self) -> TestSignatureScheme {
-    TestSignatureScheme
+    TestSignatureScheme(self.2.verifications.clone())
   }
 
   fn weights(&self) -> TestWeights {
-    TestWeights
+    self.2.weights.unwrap_or_default()
   }
 
   async fn broadcast(&mut self, msg: SignedMessageFor<Self>) {
-    for (messages, _) in self.1.write().await.iter_mut() {
+    for (messages, _, _) in self.1.write().await.iter_mut() {
       messages.send(msg.clone()).await.unwrap();
     }
   }
 
-  async fn slash(&mut self, _: TestValidatorId) {
-    dbg!("Slash");
-    todo!()
+  async fn slash(&mut self, validator: TestValidatorId, reason: SlashReason) {
+    dbg!("Slash", validator, reason);
+    self.2.slashes.write().await.push((validator, reason));
+  }
+
+  async fn store_commit(
+    &mut self,
+    _: BlockNumber,
+    id: TestBlockId,
+    commit: &Commit<TestSignatureScheme>,
+  ) {
+    self.2.stored_commits.write().await.push((self.0, id, commit.clone()));
+  }
+
+  fn precommit_without_prevote(
+    &mut self,
+    validator: TestValidatorId,
+    block: BlockNumber,
+    round: RoundNumber,
+  ) {
+    self.2.precommits_without_prevotes.try_write().unwrap().push((validator, block, round));
+  }
+
+  fn should_propose(&mut self, _: BlockNumber) -> bool {
+    !self.2.decline_proposals.load(Ordering::SeqCst)
+  }
+
+  async fn propose(&mut self, block: BlockNumber, _: RoundNumber) -> Option<TestBlock> {
+    if !self.2.slow_proposals.load(Ordering::SeqCst) {
+      return None;
+    }
+    // Take longer than the propose timeout
+    sleep(Duration::from_secs(30)).await;
+    Some(TestBlock::new(u32::try_from(block.0).unwrap()))
+  }
+
+  fn quorum_reachable(&self) -> bool {
+    !self.2.quorum_unreachable.load(Ordering::SeqCst)
+  }
+
+  fn on_stall(&mut self, block: BlockNumber, round: RoundNumber) {
+    // on_stall isn't async, so the lock can't be awaited
+    self.2.stalls.try_write().unwrap().push((self.0, block, round));
+  }
+
+  fn precommit_timeout(&mut self, block: BlockNumber, round: RoundNumber) {
+    self.2.precommit_timeouts.try_write().unwrap().push((self.0, block, round));
+  }
+
+  #[cfg(feature = "export")]
+  fn export_log(&mut self, block: BlockNumber, log: Vec<SignedMessageFor<Self>>) {
+    self.2.exported_logs.try_write().unwrap().push((block, log));
+  }
+
+  fn max_round(&self) -> RoundNumber {
+    self.2.max_round.unwrap_or(RoundNumber(u32::MAX))
+  }
+
+  fn is_halted(&self) -> bool {
+    self.2.halted.load(Ordering::SeqCst)
   }
 
   async fn validate(&mut self, block: &TestBlock) -> Result<(), BlockError> {
+    self.2.validations.fetch_add(1, Ordering::SeqCst);
     block.valid
   }
 
@@ -144,34 +286,1129 @@ impl Network for TestNetwork {
     dbg!("Adding ", &block);
     assert!(block.valid.is_ok());
     assert!(self.verify_commit(block.id(), &commit));
-    Some(TestBlock { id: (u32::from_le_bytes(block.id) + 1).to_le_bytes(), valid: Ok(()) })
+    self.2.added_blocks.write().await.push((self.0, block.id(), commit));
+    Some(TestBlock::new(u32::from_le_bytes(block.id) + 1))
   }
 }
 
 impl TestNetwork {
-  async fn new(validators: usize) -> Arc<RwLock<Vec<(MessageSender<Self>, StepSender<Self>)>>> {
+  #[allow(clippy::type_complexity)]
+  async fn new(
+    validators: usize,
+  ) -> (Arc<RwLock<Vec<(MessageSender<Self>, StepSender<Self>, SignerSender<Self>)>>>, Events) {
+    Self::with_events(validators, Events::default()).await
+  }
+
+  #[allow(clippy::type_complexity)]
+  async fn with_events(
+    validators: usize,
+    events: Events,
+  ) -> (Arc<RwLock<Vec<(MessageSender<Self>, StepSender<Self>, SignerSender<Self>)>>>, Events) {
     let arc = Arc::new(RwLock::new(vec![]));
     {
       let mut write = arc.write().await;
       for i in 0 .. validators {
         let i = u16::try_from(i).unwrap();
-        let TendermintHandle { messages, machine, step } = TendermintMachine::new(
-          TestNetwork(i, arc.clone()),
+        let TendermintHandle { messages, machine, step, signer, .. } = TendermintMachine::new(
+          TestNetwork(i, arc.clone(), events.clone()),
           BlockNumber(1),
-          SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs(),
-          TestBlock { id: 1u32.to_le_bytes(), valid: Ok(()) },
+          now(),
+          TestBlock::new(1),
         )
         .await;
         tokio::task::spawn(machine.run());
-        write.push((messages, step));
+        write.push((messages, step, signer));
       }
     }
-    arc
+    (arc, events)
   }
 }
 
+// Craft a signed message, as messages aren't publicly constructible
+// This relies on the encoding of a Message being its fields in order, with the data being the
+// index of its variant followed by the variant's fields
+async fn signed(
+  sender: TestValidatorId,
+  block: BlockNumber,
+  round: RoundNumber,
+  data: Vec<u8>,
+) -> SignedMessageFor<TestNetwork> {
+  let msg = [(sender, block, round).encode(), data].concat();
+  let sig = TestSigner(sender, 0).sign(&msg).await;
+  SignedMessageFor::<TestNetwork>::decode(&mut [msg, sig.to_vec()].concat().as_slice()).unwrap()
+}
+
+fn proposal(vr: Option<RoundNumber>, block: TestBlock) -> Vec<u8> {
+  (0u8, vr, block).encode()
+}
+
+fn prevote(id: Option<TestBlockId>) -> Vec<u8> {
+  (1u8, id).encode()
+}
+
+// Sign a precommit for the specified block, in a round ending at the specified time
+async fn precommit(signer: TestSigner, end_time: u64, id: TestBlockId) -> Vec<u8> {
+  let sig = signer.sign(&[end_time.to_le_bytes().as_ref(), id.as_ref()].concat()).await;
+  (2u8, Some((id, sig))).encode()
+}
+
+// Create a lone machine for the specified validator, working on block 2, whose broadcasts are sent
+// to the returned channel
+async fn lone_machine(
+  validator: TestValidatorId,
+  events: Events,
+  last_time: u64,
+  proposal: TestBlock,
+) -> (TendermintHandle<TestNetwork>, mpsc::UnboundedReceiver<SignedMessageFor<TestNetwork>>) {
+  let (messages, observed) = mpsc::unbounded();
+  let (step, _) = mpsc::channel(1);
+  let (signer, _) = mpsc::unbounded();
+  let network = Arc::new(RwLock::new(vec![(messages, step, signer)]));
+  let handle = TendermintMachine::new(
+    TestNetwork(validator, network, events),
+    BlockNumber(1),
+    last_time,
+    proposal,
+  )
+  .await;
+  (handle, observed)
+}
+
 #[tokio::test]
 async fn test() {
   TestNetwork::new(4).await;
   sleep(Duration::from_secs(30)).await;
 }
+
+#[tokio::test]
+async fn store_commit() {
+  let (_, events) = TestNetwork::new(4).await;
+  sleep(Duration::from_secs(10)).await;
+
+  let added_blocks = events.added_blocks.read().await;
+  assert!(!added_blocks.is_empty());
+  for added in added_blocks.iter() {
+    assert!(events.stored_commits.read().await.contains(added));
+  }
+}
+
+#[tokio::test]
+async fn non_canonical_commit() {
+  let (network, events) = TestNetwork::new(4).await;
+  sleep(Duration::from_secs(10)).await;
+
+  let (_, id, commit) = events.added_blocks.read().await[0].clone();
+  let network = TestNetwork(0, network, events);
+  assert!(network.verify_commit(id, &commit));
+
+  // Duplicating a validator, which would inflate the commit's weight, is rejected
+  let mut duplicated = commit.clone();
+  duplicated.validators.push(duplicated.validators[0]);
+  duplicated.signature.push(duplicated.signature[0]);
+  assert!(!network.verify_commit(id, &duplicated));
+
+  // As is listing the validators out of order
+  let mut reordered = commit;
+  reordered.validators.reverse();
+  reordered.signature.reverse();
+  assert!(!network.verify_commit(id, &reordered));
+}
+
+#[tokio::test]
+async fn verify_commit_without_block() {
+  let (_, events) = TestNetwork::new(4).await;
+  sleep(Duration::from_secs(10)).await;
+  let (_, id, commit) = events.added_blocks.read().await[0].clone();
+
+  // A network which has never seen any blocks can verify the commit from the ID alone
+  let network = TestNetwork(0, Arc::new(RwLock::new(vec![])), Events::default());
+  assert!(network.verify_commit(id, &commit));
+}
+
+#[tokio::test]
+async fn decline_proposals() {
+  let events = Events::default();
+  events.decline_proposals.store(true, Ordering::SeqCst);
+  let (_, events) = TestNetwork::with_events(4, events).await;
+
+  // Multiple rounds pass without a block, and without anyone being slashed for not proposing
+  sleep(Duration::from_secs(12)).await;
+  assert!(events.added_blocks.read().await.is_empty());
+  assert!(events.slashes.read().await.is_empty());
+
+  // Once proposers want to propose, blocks are produced
+  events.decline_proposals.store(false, Ordering::SeqCst);
+  sleep(Duration::from_secs(12)).await;
+  assert!(!events.added_blocks.read().await.is_empty());
+}
+
+#[tokio::test]
+async fn precommit_timeout() {
+  let events = Events::default();
+  events.decline_proposals.store(true, Ordering::SeqCst);
+  let (_, events) = TestNetwork::with_events(4, events).await;
+
+  // Without a proposal, everyone precommits nil, so the first round ends due to its precommit
+  // timeout
+  sleep(Duration::from_secs(7)).await;
+  for i in 0 .. 4 {
+    assert!(events.precommit_timeouts.read().await.contains(&(i, BlockNumber(2), RoundNumber(0))));
+  }
+  assert!(events.added_blocks.read().await.is_empty());
+}
+
+#[tokio::test]
+async fn quorum_unreachable() {
+  let events = Events::default();
+  events.quorum_unreachable.store(true, Ordering::SeqCst);
+  let (_, events) = TestNetwork::with_events(4, events).await;
+
+  // Every validator reports the stall, and no one is slashed for not proposing into it
+  sleep(Duration::from_secs(12)).await;
+  for i in 0 .. 4 {
+    assert!(events.stalls.read().await.contains(&(i, BlockNumber(2), RoundNumber(0))));
+  }
+  assert!(events.added_blocks.read().await.is_empty());
+  assert!(events.slashes.read().await.is_empty());
+}
+
+#[tokio::test]
+async fn max_round() {
+  let events = Arc::new(TestEvents { max_round: Some(RoundNumber(0)), ..Default::default() });
+  events.decline_proposals.store(true, Ordering::SeqCst);
+  let (_, events) = TestNetwork::with_events(4, events).await;
+
+  // Without a proposal, the first round ends due to its precommit timeout, yet as it's the last
+  // round, every validator stalls instead of advancing
+  sleep(Duration::from_secs(7)).await;
+  let mut stalls = events.stalls.read().await.clone();
+  stalls.sort_by_key(|(i, _, _)| *i);
+  assert_eq!(stalls, (0 .. 4).map(|i| (i, BlockNumber(2), RoundNumber(0))).collect::<Vec<_>>());
+  assert!(events.added_blocks.read().await.is_empty());
+}
+
+#[tokio::test]
+async fn halt() {
+  let events = Events::default();
+  events.halted.store(true, Ordering::SeqCst);
+  let (_, events) = TestNetwork::with_events(4, events).await;
+
+  // No blocks are finalized while halted, nor is anyone slashed for not proposing
+  sleep(Duration::from_secs(12)).await;
+  assert!(events.added_blocks.read().await.is_empty());
+  assert!(events.slashes.read().await.is_empty());
+
+  // Once the halt is lifted, the still running machines resume finalizing blocks
+  events.halted.store(false, Ordering::SeqCst);
+  sleep(Duration::from_secs(12)).await;
+  assert!(!events.added_blocks.read().await.is_empty());
+  assert!(events.slashes.read().await.is_empty());
+}
+
+#[tokio::test]
+async fn slow_proposals() {
+  let events = Events::default();
+  events.slow_proposals.store(true, Ordering::SeqCst);
+  let (_, events) = TestNetwork::with_events(4, events).await;
+
+  // Proposals which aren't built in time fall back to the proposal from add_block, so blocks are
+  // still produced, without anyone being slashed for not proposing
+  sleep(Duration::from_secs(12)).await;
+  assert!(!events.added_blocks.read().await.is_empty());
+  assert!(events.slashes.read().await.is_empty());
+}
+
+// Logger capturing the messages logged for rejected messages
+struct RejectionLogger;
+static REJECTIONS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+impl log::Log for RejectionLogger {
+  fn enabled(&self, metadata: &log::Metadata) -> bool {
+    metadata.target() == "tendermint::rejected"
+  }
+  fn log(&self, record: &log::Record) {
+    if self.enabled(record.metadata()) {
+      REJECTIONS.lock().unwrap().push(record.args().to_string());
+    }
+  }
+  fn flush(&self) {}
+}
+
+#[tokio::test]
+async fn log_rejection() {
+  log::set_logger(&RejectionLogger).unwrap();
+  log::set_max_level(log::LevelFilter::Debug);
+
+  // Send validator 0, working on block 2, a prevote for block 5
+  let (network, _) = TestNetwork::new(1).await;
+  let msg = signed(3, BlockNumber(5), RoundNumber(0), prevote(None)).await;
+  network.write().await[0].0.send(msg).await.unwrap();
+
+  sleep(Duration::from_secs(1)).await;
+  assert!(REJECTIONS
+    .lock()
+    .unwrap()
+    .contains(&"sender=3 block=5 round=0 step=Prevote reason=WrongHeight".to_string()));
+}
+
+#[tokio::test]
+async fn same_pass_finalization() {
+  let last_time = now();
+  let events = Events::default();
+  let (TendermintHandle { mut messages, machine, step: _step, .. }, _observed) =
+    lone_machine(3, events.clone(), last_time, TestBlock::new(1)).await;
+  tokio::task::spawn(machine.run());
+
+  // Validator 2 proposes, and validators 0 and 1 precommit to it, which isn't a quorum
+  let block = TestBlock::new(1);
+  let msg = signed(2, BlockNumber(2), RoundNumber(0), proposal(None, block.clone())).await;
+  messages.send(msg).await.unwrap();
+  for sender in [0, 1] {
+    let precommit = precommit(TestSigner(sender, 0), last_time + 5, block.id()).await;
+    messages.send(signed(sender, BlockNumber(2), RoundNumber(0), precommit).await).await.unwrap();
+  }
+  sleep(Duration::from_millis(500)).await;
+  assert!(events.added_blocks.read().await.is_empty());
+
+  // Validator 2's precommit completes the quorum, finalizing the block when it's handled, without
+  // any further message being needed
+  let precommit = precommit(TestSigner(2, 0), last_time + 5, block.id()).await;
+  messages.send(signed(2, BlockNumber(2), RoundNumber(0), precommit).await).await.unwrap();
+  sleep(Duration::from_millis(500)).await;
+  let added = events.added_blocks.read().await;
+  assert_eq!(added.len(), 1);
+  assert_eq!(added[0].1, block.id());
+}
+
+#[tokio::test]
+async fn far_future_proposal() {
+  let last_time = now();
+  let events = Events::default();
+  let (TendermintHandle { mut messages, machine, step: _step, .. }, _observed) =
+    lone_machine(3, events.clone(), last_time, TestBlock::new(1)).await;
+  tokio::task::spawn(machine.run());
+
+  // Validator 2 proposes a block timestamped at the end of time, which can't be retried before
+  // the round ends, and accordingly is dropped
+  let mut block = TestBlock::new(2);
+  block.time = u64::MAX;
+  let msg = signed(2, BlockNumber(2), RoundNumber(0), proposal(None, block)).await;
+  messages.send(msg).await.unwrap();
+
+  // The machine is still running, accepting a proposal which everyone precommits to
+  let block = TestBlock::new(1);
+  let msg = signed(2, BlockNumber(2), RoundNumber(0), proposal(None, block.clone())).await;
+  messages.send(msg).await.unwrap();
+  for sender in [0, 1, 2] {
+    let precommit = precommit(TestSigner(sender, 0), last_time + 5, block.id()).await;
+    messages.send(signed(sender, BlockNumber(2), RoundNumber(0), precommit).await).await.unwrap();
+  }
+
+  sleep(Duration::from_secs(1)).await;
+  assert!(events.slashes.read().await.is_empty());
+  let added = events.added_blocks.read().await;
+  assert_eq!(added.len(), 1);
+  assert_eq!(added[0].1, block.id());
+}
+
+#[tokio::test]
+async fn future_proposal() {
+  let last_time = now();
+  let events = Events::default();
+  let (TendermintHandle { mut messages, machine, step: _step, .. }, _observed) =
+    lone_machine(3, events.clone(), last_time, TestBlock::new(1)).await;
+  tokio::task::spawn(machine.run());
+
+  // Validator 2 proposes a block a few seconds past the allowed drift, which everyone precommits to
+  let mut block = TestBlock::new(1);
+  block.time = now() + 63;
+  let msg = signed(2, BlockNumber(2), RoundNumber(0), proposal(None, block.clone())).await;
+  messages.send(msg).await.unwrap();
+  for sender in [0, 1, 2] {
+    let precommit = precommit(TestSigner(sender, 0), last_time + 5, block.id()).await;
+    messages.send(signed(sender, BlockNumber(2), RoundNumber(0), precommit).await).await.unwrap();
+  }
+
+  // Our clock may be behind, so the proposal isn't slashed for, instead being retried once it's
+  // within bounds
+  sleep(Duration::from_secs(1)).await;
+  assert!(events.added_blocks.read().await.is_empty());
+  sleep(Duration::from_secs(4)).await;
+  assert!(events.slashes.read().await.is_empty());
+  assert_eq!(events.added_blocks.read().await[0].1, block.id());
+}
+
+// Have validator 3 propose for round 1 of block 2, claiming round 0 was valid for its block
+// Returns whether validator 3 was slashed, and whether validator 0 prevoted nil in round 1, if it
+// prevoted
+async fn valid_round(round_zero_prevotes: bool) -> (bool, Option<bool>) {
+  let events = Events::default();
+  let (TendermintHandle { mut messages, machine, step: _step, .. }, mut observed) =
+    lone_machine(0, events.clone(), now(), TestBlock::new(1)).await;
+  tokio::task::spawn(machine.run());
+  let block = TestBlock::new(2);
+
+  let mut msgs = vec![];
+  if round_zero_prevotes {
+    // Prevotes for nil from validators 1 and 2 make consensus on the block impossible
+    for sender in [1, 2] {
+      msgs.push(signed(sender, BlockNumber(2), RoundNumber(0), prevote(None)).await);
+    }
+  }
+  // Sufficient participation in round 1 for validator 0 to jump to it
+  for sender in [1, 2] {
+    msgs.push(signed(sender, BlockNumber(2), RoundNumber(1), prevote(None)).await);
+  }
+  msgs.push(signed(3, BlockNumber(2), RoundNumber(1), proposal(Some(RoundNumber(0)), block)).await);
+  for msg in msgs {
+    messages.send(msg).await.unwrap();
+  }
+
+  sleep(Duration::from_secs(1)).await;
+  let slashed = events.slashes.read().await.contains(&(3, SlashReason::InvalidMessage));
+  let mut prevoted = None;
+  let round_one = (0u16, BlockNumber(2), RoundNumber(1)).encode();
+  while let Ok(Some(msg)) = observed.try_next() {
+    let encoded = msg.encode();
+    // The step is the variant index of the data, after the sender, block, and round
+    if encoded.starts_with(&round_one) && (encoded[14] == 1) {
+      prevoted = Some(encoded[14 ..].starts_with(&prevote(None)));
+    }
+  }
+  (slashed, prevoted)
+}
+
+#[tokio::test]
+async fn valid_round_without_consensus() {
+  // The proposer isn't slashed, as equivocating prevoters could have framed them, yet we prevote
+  // nil
+  assert_eq!(valid_round(true).await, (false, Some(true)));
+}
+
+#[tokio::test]
+async fn valid_round_not_yet_seen() {
+  // We wait for more of the valid round's messages before prevoting
+  assert_eq!(valid_round(false).await, (false, None));
+}
+
+#[test]
+fn snapshot_proposer() {
+  // The snapshot's validators are listed in reverse, which doesn't affect the round robin
+  let weights = TestWeights::default();
+  let snapshot =
+    ValidatorSetSnapshot::new((0 .. 4).rev().map(|i| (i, weights.weight(i))).collect());
+  assert_eq!(snapshot.total_weight(), weights.total_weight());
+  for number in 1 .. 3 {
+    for round in 0 .. 8 {
+      let (number, round) = (BlockNumber(number), RoundNumber(round));
+      assert_eq!(snapshot.proposer(number, round), weights.proposer(number, round));
+    }
+  }
+}
+
+#[test]
+fn proposer_determinism() {
+  // Every order the validators may be iterated in
+  let orders = [[0, 1, 2], [0, 2, 1], [1, 0, 2], [1, 2, 0], [2, 0, 1], [2, 1, 0]];
+  for number in 1 .. 3 {
+    for round in 0 .. 8 {
+      let (number, round) = (BlockNumber(number), RoundNumber(round));
+      let proposers = orders
+        .map(|order| proposer(&order.map(|i: TestValidatorId| (i, 1)), number, round).unwrap());
+      assert!(proposers.iter().all(|proposer| *proposer == proposers[0]));
+    }
+  }
+
+  // Each validator is selected proportionally to their weight
+  let validators = [(0, 1), (1, 2), (2, 1)];
+  let mut selected = [0; 3];
+  for round in 0 .. 8 {
+    let selection = proposer(&validators, BlockNumber(0), RoundNumber(round)).unwrap();
+    selected[usize::from(selection)] += 1;
+  }
+  assert_eq!(selected, [2, 4, 2]);
+
+  // A proposer can't be selected from a set without weight
+  assert_eq!(
+    proposer::<TestValidatorId>(&[], BlockNumber(0), RoundNumber(0)),
+    Err(ProposerError::NoValidators)
+  );
+  assert_eq!(
+    proposer::<TestValidatorId>(&[(0, 0), (1, 0)], BlockNumber(0), RoundNumber(0)),
+    Err(ProposerError::NoWeight)
+  );
+}
+
+#[test]
+fn round_number_overflow() {
+  assert_eq!(RoundNumber(0).next(), Some(RoundNumber(1)));
+  assert_eq!(RoundNumber(u32::MAX - 1).next(), Some(RoundNumber(u32::MAX)));
+  // Advancing past the last round halts instead of wrapping back to round 0
+  assert_eq!(RoundNumber(u32::MAX).next(), None);
+}
+
+#[test]
+fn recent_blocks() {
+  const K: usize = 4;
+  let mut recent = RecentBlocks::new(K);
+  for number in 1 ..= (K as u64 + 1) {
+    recent.insert(BlockNumber(number), TestBlock::new(number as u32).id());
+  }
+
+  // The oldest block was evicted
+  assert!(!recent.contains(&TestBlock::new(1).id()));
+  // The K most recent blocks were retained
+  for number in 2 ..= (K as u64 + 1) {
+    assert_eq!(recent.get(&TestBlock::new(number as u32).id()), Some(BlockNumber(number)));
+  }
+}
+
+#[tokio::test]
+async fn rotate_signer() {
+  let (network, events) = TestNetwork::new(4).await;
+  // Observe every message broadcast
+  let (messages, mut observed) = mpsc::unbounded();
+  let (step, _) = mpsc::channel(1);
+  let (signer, _) = mpsc::unbounded();
+  network.write().await.push((messages, step, signer));
+
+  // Rotate validator 0's key while block 2, the first block, is still being worked on
+  network.write().await[0].2.send(TestSigner(0, 1)).await.unwrap();
+  sleep(Duration::from_secs(12)).await;
+  // The block after it was also finalized, with the new key
+  assert!(events.added_blocks.read().await.iter().any(|(_, id, _)| *id == 2u32.to_le_bytes()));
+
+  let mut rotated = false;
+  while let Ok(Some(msg)) = observed.try_next() {
+    if msg.sender() != 0 {
+      continue;
+    }
+    assert!(msg.verify_signature(&TestSignatureScheme::default()));
+    // The key is the third byte of the signature, which is the encoding's last 32 bytes
+    let encoded = msg.encode();
+    let key = encoded[encoded.len() - 32 + 2];
+    if msg.block() == BlockNumber(2) {
+      assert_eq!(key, 0);
+    } else {
+      assert_eq!(key, 1);
+      rotated = true;
+    }
+  }
+  assert!(rotated);
+}
+
+#[tokio::test]
+async fn coalesce_steps() {
+  let (TendermintHandle { mut step, machine, messages: _messages, .. }, mut observed) =
+    lone_machine(0, Events::default(), now(), TestBlock::new(2)).await;
+
+  // Flood the step channel with commits for blocks 2 ..= 12 before the machine runs
+  for number in 2 ..= 12 {
+    let commit = Commit { end_time: now(), validators: vec![], signature: vec![] };
+    step.send((BlockNumber(number), commit, None)).await.unwrap();
+  }
+  tokio::task::spawn(machine.run());
+
+  // The machine jumps straight to block 13, prevoting nil in it once its propose timeout expires,
+  // without ever acting on the blocks in-between
+  sleep(Duration::from_secs(5)).await;
+  let mut prevoted = false;
+  while let Ok(Some(msg)) = observed.try_next() {
+    assert_eq!(msg.block(), BlockNumber(13));
+    prevoted = true;
+  }
+  assert!(prevoted);
+}
+
+#[tokio::test]
+async fn cascading_broadcasts() {
+  // Validator 2 is the proposer for block 2, round 0
+  let proposal = TestBlock::new(1);
+  let (TendermintHandle { mut messages, machine, step: _step, .. }, mut observed) =
+    lone_machine(2, Events::default(), now(), proposal.clone()).await;
+
+  // Once its own prevote is handled, these two prevotes cause it to precommit
+  for sender in [0, 1] {
+    let msg = signed(sender, BlockNumber(2), RoundNumber(0), prevote(Some(proposal.id()))).await;
+    messages.send(msg).await.unwrap();
+  }
+  tokio::task::spawn(machine.run());
+
+  // The proposal, prevote, and precommit are all broadcast well before the propose timeout, each
+  // handled off the queue in turn instead of recursively
+  // The step is the variant index of the data, after the sender, block, and round
+  let mut steps = vec![];
+  tokio::time::timeout(Duration::from_secs(2), async {
+    while steps.len() < 3 {
+      steps.push(observed.next().await.unwrap().encode()[14]);
+    }
+  })
+  .await
+  .unwrap();
+  assert_eq!(steps, vec![0, 1, 2]);
+}
+
+#[tokio::test]
+async fn drop_echoed_messages() {
+  // Validator 2 proposes, and validators 0 and 1 prevote for it, causing it to precommit
+  let proposal = TestBlock::new(1);
+  let events = Events::default();
+  let (TendermintHandle { mut messages, machine, step: _step, .. }, mut observed) =
+    lone_machine(2, events.clone(), now(), proposal.clone()).await;
+  let mut prevotes = vec![];
+  for sender in [0, 1] {
+    let msg = signed(sender, BlockNumber(2), RoundNumber(0), prevote(Some(proposal.id()))).await;
+    messages.send(msg.clone()).await.unwrap();
+    prevotes.push(msg);
+  }
+  tokio::task::spawn(machine.run());
+
+  let precommit = tokio::time::timeout(Duration::from_secs(2), async {
+    loop {
+      let msg = observed.next().await.unwrap();
+      // The step is the variant index of the data, after the sender, block, and round
+      if msg.encode()[14] == 2 {
+        break msg;
+      }
+    }
+  })
+  .await
+  .unwrap();
+
+  // Echo its own precommit back to it, followed by a duplicate of validator 0's prevote
+  // Only the latter is verified, the precommit being dropped before its signature is checked
+  sleep(Duration::from_millis(100)).await;
+  let verifications = events.verifications.load(Ordering::SeqCst);
+  messages.send(precommit).await.unwrap();
+  messages.send(prevotes.swap_remove(0)).await.unwrap();
+  sleep(Duration::from_secs(1)).await;
+  assert_eq!(events.verifications.load(Ordering::SeqCst), verifications + 1);
+}
+
+#[tokio::test]
+async fn echoed_message_from_before_resume() {
+  // Validator 3 proposes round 1 of block 2, which it resumes at
+  let events = Events::default();
+  let (TendermintHandle { mut messages, mut machine, step: _step, .. }, _observed) =
+    lone_machine(3, events.clone(), now(), TestBlock::new(1)).await;
+  machine.resume(RoundNumber(1), None, None).await;
+  tokio::task::spawn(machine.run());
+  sleep(Duration::from_millis(500)).await;
+
+  // Before resuming, it proposed a distinct block in round 1, which is echoed back to it
+  // As it was never logged, it's handled, revealing the equivocation
+  let msg = signed(3, BlockNumber(2), RoundNumber(1), proposal(None, TestBlock::new(2))).await;
+  messages.send(msg).await.unwrap();
+  sleep(Duration::from_secs(1)).await;
+  assert_eq!(*events.slashes.read().await, vec![(3, SlashReason::Equivocation)]);
+}
+
+#[tokio::test]
+async fn rebroadcast_proposal() {
+  // Validator 2 is the proposer for block 2, round 0, which won't end as no one else participates
+  let (TendermintHandle { machine, messages: _messages, step: _step, .. }, mut observed) =
+    lone_machine(2, Events::default(), now(), TestBlock::new(1)).await;
+  tokio::task::spawn(machine.run());
+
+  // The proposal is broadcast immediately, then re-broadcast every two seconds
+  sleep(Duration::from_secs(5)).await;
+  let mut proposals = vec![];
+  while let Ok(Some(msg)) = observed.try_next() {
+    let encoded = msg.encode();
+    // The step is the variant index of the data, after the sender, block, and round
+    if encoded[14] == 0 {
+      proposals.push(encoded);
+    }
+  }
+  assert_eq!(proposals.len(), 3);
+  // Each is the exact same signed message
+  assert!(proposals.iter().all(|proposal| proposal == &proposals[0]));
+}
+
+#[tokio::test]
+async fn rebroadcast_proposal_late_join() {
+  let last_time = now();
+  let block = TestBlock::new(1);
+  let (TendermintHandle { machine, messages: _messages, step: _step, .. }, mut observed) =
+    lone_machine(2, Events::default(), last_time, block.clone()).await;
+  tokio::task::spawn(machine.run());
+
+  // Validator 3 joins after the proposal was first broadcast, missing it
+  let first = observed.next().await.unwrap();
+  assert_eq!(first.encode()[14], 0);
+  let events = Events::default();
+  let (TendermintHandle { mut messages, machine, step: _step, .. }, _observed) =
+    lone_machine(3, events.clone(), last_time, TestBlock::new(1)).await;
+  tokio::task::spawn(machine.run());
+
+  // It receives a quorum of precommits, yet can't finalize without the proposal
+  for sender in [0, 1, 2] {
+    let precommit = precommit(TestSigner(sender, 0), last_time + 5, block.id()).await;
+    messages.send(signed(sender, BlockNumber(2), RoundNumber(0), precommit).await).await.unwrap();
+  }
+  sleep(Duration::from_millis(500)).await;
+  assert!(events.added_blocks.read().await.is_empty());
+
+  // Forward everything the proposer broadcasts from here on, until the re-broadcast proposal
+  loop {
+    let msg = observed.next().await.unwrap();
+    let step = msg.encode()[14];
+    messages.send(msg).await.unwrap();
+    if step == 0 {
+      break;
+    }
+  }
+
+  // With the re-broadcast proposal, validator 3 finalizes the round it otherwise would've missed
+  sleep(Duration::from_secs(1)).await;
+  let added = events.added_blocks.read().await;
+  assert_eq!(added.len(), 1);
+  assert_eq!(added[0].1, block.id());
+}
+
+#[tokio::test]
+async fn deterministic_commit() {
+  let last_time = now();
+  let block = TestBlock::new(1);
+
+  // Have two machines receive the same precommits, in differing orders, before the proposal
+  let mut commits = vec![];
+  for order in [[0, 1, 2], [2, 0, 1]] {
+    let events = Events::default();
+    let (TendermintHandle { mut messages, machine, step: _step, .. }, _observed) =
+      lone_machine(3, events.clone(), last_time, TestBlock::new(1)).await;
+    for sender in order {
+      // Round 0 ends a block time after the last block
+      let precommit = precommit(TestSigner(sender, 0), last_time + 5, block.id()).await;
+      messages.send(signed(sender, BlockNumber(2), RoundNumber(0), precommit).await).await.unwrap();
+    }
+    let msg = signed(2, BlockNumber(2), RoundNumber(0), proposal(None, block.clone())).await;
+    messages.send(msg).await.unwrap();
+    tokio::task::spawn(machine.run());
+
+    sleep(Duration::from_secs(2)).await;
+    let (_, id, commit) = events.added_blocks.read().await[0].clone();
+    assert_eq!(id, block.id());
+    commits.push(commit.encode());
+  }
+  assert_eq!(commits[0], commits[1]);
+}
+
+#[tokio::test]
+async fn next_proposer() {
+  let (TendermintHandle { mut messages, machine, step: _step, next_proposer, .. }, _observed) =
+    lone_machine(3, Events::default(), now(), TestBlock::new(1)).await;
+  tokio::task::spawn(machine.run());
+
+  // Validator 3 proposes rounds 1, 5, ... of block 2, so it proposes next during rounds 0, 4, ...
+  assert!(*next_proposer.borrow());
+  // Jump to rounds 1 and 4 via prevotes from enough validators to justify doing so
+  for (round, expected) in [(1, false), (4, true)] {
+    for sender in [0, 1] {
+      let msg = signed(sender, BlockNumber(2), RoundNumber(round), prevote(None)).await;
+      messages.send(msg).await.unwrap();
+    }
+    sleep(Duration::from_secs(1)).await;
+    assert_eq!(*next_proposer.borrow(), expected);
+  }
+}
+
+#[tokio::test]
+async fn stale_precommit() {
+  let last_time = now();
+  let events = Events::default();
+  let (TendermintHandle { mut messages, machine, step: _step, .. }, _observed) =
+    lone_machine(3, events.clone(), last_time, TestBlock::new(1)).await;
+  tokio::task::spawn(machine.run());
+
+  // Validator 1 precommits to one block in round 1, then to another in round 0, which would
+  // replace their newer precommit if accepted
+  // Rounds 0 and 1 end 5 and 15 seconds after the last block
+  for (round, id, end_time) in [(1, [1; 4], last_time + 15), (0, [0; 4], last_time + 5)] {
+    let precommit = precommit(TestSigner(1, 0), end_time, id).await;
+    messages.send(signed(1, BlockNumber(2), RoundNumber(round), precommit).await).await.unwrap();
+  }
+
+  sleep(Duration::from_secs(1)).await;
+  assert_eq!(*events.slashes.read().await, vec![(1, SlashReason::Equivocation)]);
+}
+
+#[tokio::test]
+async fn stale_precommit_same_block() {
+  let last_time = now();
+  let events = Events::default();
+  let (TendermintHandle { mut messages, machine, step: _step, .. }, _observed) =
+    lone_machine(3, events.clone(), last_time, TestBlock::new(1)).await;
+  tokio::task::spawn(machine.run());
+
+  // Validator 1 precommits to the same block in round 1, then in round 0
+  // As gossip doesn't guarantee order, this is how an honest, locked validator's precommits may
+  // arrive, so it isn't slashed for
+  for (round, end_time) in [(1, last_time + 15), (0, last_time + 5)] {
+    let precommit = precommit(TestSigner(1, 0), end_time, [1; 4]).await;
+    messages.send(signed(1, BlockNumber(2), RoundNumber(round), precommit).await).await.unwrap();
+  }
+
+  // A precommit to a distinct block is still detected, despite the stale precommit
+  let precommit = precommit(TestSigner(1, 0), last_time + 30, [0; 4]).await;
+  messages.send(signed(1, BlockNumber(2), RoundNumber(2), precommit).await).await.unwrap();
+
+  sleep(Duration::from_secs(1)).await;
+  assert_eq!(*events.slashes.read().await, vec![(1, SlashReason::Equivocation)]);
+}
+
+#[tokio::test]
+async fn aggregation_failure() {
+  let last_time = now();
+  let events = Events::default();
+  let (TendermintHandle { mut messages, machine, step: _step, .. }, _observed) =
+    lone_machine(3, events.clone(), last_time, TestBlock::new(1)).await;
+  tokio::task::spawn(machine.run());
+
+  // Validator 2 proposes, and validators 0 and 1 prevote for it, causing us to precommit
+  let block = TestBlock::new(1);
+  let msg = signed(2, BlockNumber(2), RoundNumber(0), proposal(None, block.clone())).await;
+  messages.send(msg).await.unwrap();
+  for sender in [0, 1] {
+    let msg = signed(sender, BlockNumber(2), RoundNumber(0), prevote(Some(block.id()))).await;
+    messages.send(msg).await.unwrap();
+  }
+
+  // Validator 0's precommit fails to aggregate, so it's dropped and they're slashed, with the
+  // commit then formed once validator 2 also precommits
+  for (sender, key) in [(0, u8::MAX), (1, 0), (2, 0)] {
+    let precommit = precommit(TestSigner(sender, key), last_time + 5, block.id()).await;
+    messages.send(signed(sender, BlockNumber(2), RoundNumber(0), precommit).await).await.unwrap();
+  }
+
+  sleep(Duration::from_secs(1)).await;
+  assert_eq!(*events.slashes.read().await, vec![(0, SlashReason::InvalidMessage)]);
+  let (_, id, commit) = events.added_blocks.read().await[0].clone();
+  assert_eq!(id, block.id());
+  assert_eq!(commit.validators, vec![1, 2, 3]);
+}
+
+#[tokio::test]
+async fn future_valid_round() {
+  let events = Events::default();
+  let (TendermintHandle { mut messages, machine, step: _step, .. }, _observed) =
+    lone_machine(3, events.clone(), now(), TestBlock::new(1)).await;
+  tokio::task::spawn(machine.run());
+
+  // A proposal claiming its own round as its valid round is rejected before it's validated
+  let vr = Some(RoundNumber(0));
+  let msg = signed(2, BlockNumber(2), RoundNumber(0), proposal(vr, TestBlock::new(1))).await;
+  messages.send(msg).await.unwrap();
+
+  sleep(Duration::from_secs(1)).await;
+  assert_eq!(*events.slashes.read().await, vec![(2, SlashReason::InvalidMessage)]);
+  assert_eq!(events.validations.load(Ordering::SeqCst), 0);
+}
+
+#[tokio::test]
+async fn minimal_quorum() {
+  let last_time = now();
+  let events =
+    Arc::new(TestEvents { weights: Some(TestWeights([3, 2, 2, 1], 5)), ..Default::default() });
+  let (TendermintHandle { mut messages, machine, step: _step, .. }, _observed) =
+    lone_machine(3, events.clone(), last_time, TestBlock::new(1)).await;
+  tokio::task::spawn(machine.run());
+
+  let block = TestBlock::new(1);
+  let msg = signed(2, BlockNumber(2), RoundNumber(0), proposal(None, block.clone())).await;
+  messages.send(msg).await.unwrap();
+
+  // Validators 1 and 2 don't meet the threshold alone, yet once validator 0 also precommits, the
+  // heaviest validators suffice, with the tie between 1 and 2 broken by ID
+  for sender in [2, 1, 0] {
+    let precommit = precommit(TestSigner(sender, 0), last_time + 5, block.id()).await;
+    messages.send(signed(sender, BlockNumber(2), RoundNumber(0), precommit).await).await.unwrap();
+  }
+
+  sleep(Duration::from_secs(1)).await;
+  let (_, id, commit) = events.added_blocks.read().await[0].clone();
+  assert_eq!(id, block.id());
+  assert_eq!(commit.validators, vec![0, 1]);
+}
+
+#[tokio::test]
+async fn build_commit() {
+  let network = TestNetwork(0, Arc::new(RwLock::new(vec![])), Events::default());
+  let id = TestBlock::new(1).id();
+  let end_time = now();
+  let commit_msg = [end_time.to_le_bytes().as_ref(), id.as_ref()].concat();
+
+  // Precommits may be passed in any order
+  let mut precommits = vec![];
+  for sender in [2, 0, 1] {
+    precommits.push((sender, TestSigner(sender, 0).sign(&commit_msg).await));
+  }
+  let commit = Commit::<TestSignatureScheme>::build(end_time, &precommits).unwrap();
+  assert_eq!(commit.validators, vec![0, 1, 2]);
+  assert!(network.verify_commit(id, &commit));
+
+  // Failing to aggregate reports the index of the failing precommit, as passed
+  precommits[2].1 = TestSigner(1, u8::MAX).sign(&commit_msg).await;
+  assert_eq!(
+    Commit::<TestSignatureScheme>::build(end_time, &precommits),
+    Err(AggregateError::InvalidSignature(2))
+  );
+}
+
+#[tokio::test]
+async fn observer_doesnt_slash() {
+  // Round 8 is the first round not proposing is slashed for, and starts 180 seconds after the
+  // last block, with its propose timeout 27 seconds after that
+  // Start from a last block far enough in the past that this propose timeout is imminent
+  let events = Events::default();
+  let (TendermintHandle { mut messages, machine, step: _step, .. }, _observed) =
+    lone_machine(4, events.clone(), now() - 206, TestBlock::new(1)).await;
+  tokio::task::spawn(machine.run());
+
+  // Jump to round 8, whose proposer won't propose
+  for sender in [0, 1] {
+    let msg = signed(sender, BlockNumber(2), RoundNumber(8), prevote(None)).await;
+    messages.send(msg).await.unwrap();
+  }
+
+  sleep(Duration::from_secs(3)).await;
+  assert!(events.slashes.read().await.is_empty());
+}
+
+#[tokio::test]
+async fn commit_signed_weight() {
+  let commit_msg = [0u64.to_le_bytes().as_ref(), &[0; 4]].concat();
+  let mut precommits = vec![];
+  for sender in [0, 1, 2] {
+    precommits.push((sender, TestSigner(sender, 0).sign(&commit_msg).await));
+  }
+  // Each validator has a weight of 1, out of a total of 4
+  let commit = Commit::<TestSignatureScheme>::build(0, &precommits).unwrap();
+  assert_eq!(commit.signed_weight(&TestWeights::default()), 3);
+  assert_eq!(commit.signed_fraction(&TestWeights::default()), 0.75);
+}
+
+#[tokio::test]
+async fn resume() {
+  // Validator 1 proposes round 7 of block 2
+  let (TendermintHandle { mut machine, messages: _messages, step: _step, .. }, mut observed) =
+    lone_machine(1, Events::default(), now(), TestBlock::new(1)).await;
+  let valid = TestBlock::new(2);
+  machine.resume(RoundNumber(7), None, Some((RoundNumber(3), valid.clone()))).await;
+  tokio::task::spawn(machine.run());
+
+  // It immediately re-proposes its valid block in round 7, without having waited through rounds
+  // 0 to 6
+  sleep(Duration::from_secs(1)).await;
+  let msg = observed.try_next().unwrap().unwrap().encode();
+  let expected =
+    [(1u16, BlockNumber(2), RoundNumber(7)).encode(), proposal(Some(RoundNumber(3)), valid)];
+  assert!(msg.starts_with(&expected.concat()));
+}
+
+#[tokio::test(start_paused = true)]
+async fn stall_at_max_round() {
+  // Validator 1 proposes round u32::MAX of block 2, so validator 0 waits for its propose timeout
+  let events = Events::default();
+  let (TendermintHandle { mut machine, mut messages, step: _step, .. }, mut observed) =
+    lone_machine(0, events.clone(), now(), TestBlock::new(1)).await;
+  let max = RoundNumber(u32::MAX);
+  machine.resume(max, None, None).await;
+  tokio::task::spawn(machine.run());
+
+  // Everyone else prevotes and precommits nil, so the round ends with its precommit timeout
+  let precommit = (2u8, Option::<(TestBlockId, [u8; 32])>::None).encode();
+  for sender in [1, 2, 3] {
+    messages.send(signed(sender, BlockNumber(2), max, prevote(None)).await).await.unwrap();
+    messages.send(signed(sender, BlockNumber(2), max, precommit.clone()).await).await.unwrap();
+  }
+
+  // Time is paused, so it automatically advances to this round's timeouts, however distant
+  for _ in 0 .. 1000 {
+    if !events.stalls.read().await.is_empty() {
+      break;
+    }
+    sleep(Duration::from_secs(365 * 24 * 60 * 60)).await;
+  }
+  assert_eq!(*events.precommit_timeouts.read().await, vec![(0, BlockNumber(2), max)]);
+  assert_eq!(*events.stalls.read().await, vec![(0, BlockNumber(2), max)]);
+
+  // It only ever prevoted and precommitted in this round, never wrapping back to round 0
+  sleep(Duration::from_secs(365 * 24 * 60 * 60)).await;
+  let mut broadcast = vec![];
+  while let Ok(Some(msg)) = observed.try_next() {
+    broadcast.push(msg.encode());
+  }
+  assert_eq!(broadcast.len(), 2);
+  for msg in broadcast {
+    assert!(msg.starts_with(&(0u16, BlockNumber(2), max).encode()));
+  }
+}
+
+#[tokio::test]
+async fn merge_commits() {
+  let network = TestNetwork(0, Arc::new(RwLock::new(vec![])), Events::default());
+  let id = TestBlock::new(1).id();
+  let end_time = now();
+  let commit_msg = [end_time.to_le_bytes().as_ref(), id.as_ref()].concat();
+  let commit = |validators: [u16; 3]| {
+    let commit_msg = commit_msg.clone();
+    async move {
+      let mut precommits = vec![];
+      for validator in validators {
+        precommits.push((validator, TestSigner(validator, 0).sign(&commit_msg).await));
+      }
+      Commit::<TestSignatureScheme>::build(end_time, &precommits).unwrap()
+    }
+  };
+
+  // Two commits which partially overlap merge into one covering all of their validators
+  let a = commit([0, 1, 2]).await;
+  let b = commit([1, 2, 3]).await;
+  let merged = a.merge(&b).unwrap();
+  assert_eq!(merged.validators, vec![0, 1, 2, 3]);
+  assert!(network.verify_commit(id, &merged));
+  let weights = TestWeights::default();
+  assert_eq!(merged.signed_weight(&weights), weights.total_weight());
+
+  // Commits from distinct rounds can't be merged
+  let mut other_round = b;
+  other_round.end_time += 1;
+  assert!(a.merge(&other_round).is_none());
+}
+
+#[tokio::test]
+async fn precommit_without_prevote() {
+  let events = Events::default();
+  let (TendermintHandle { mut messages, mut step, machine, .. }, _observed) =
+    lone_machine(3, events.clone(), now(), TestBlock::new(1)).await;
+  tokio::task::spawn(machine.run());
+
+  // Validator 0 precommits without prevoting, while validator 1 does both
+  let precommit = (2u8, Option::<(TestBlockId, [u8; 32])>::None).encode();
+  for (sender, data) in [(0, precommit.clone()), (1, prevote(None)), (1, precommit)] {
+    messages.send(signed(sender, BlockNumber(2), RoundNumber(0), data).await).await.unwrap();
+  }
+  sleep(Duration::from_secs(1)).await;
+  assert!(events.precommits_without_prevotes.read().await.is_empty());
+
+  // Once the machine moves past the block, solely validator 0 is reported
+  let commit = Commit { end_time: now(), validators: vec![], signature: vec![] };
+  step.send((BlockNumber(3), commit, None)).await.unwrap();
+  sleep(Duration::from_secs(1)).await;
+  assert_eq!(
+    *events.precommits_without_prevotes.read().await,
+    vec![(0, BlockNumber(2), RoundNumber(0))]
+  );
+}
+
+#[tokio::test]
+async fn invalid_block_code() {
+  // Only spawn validator 0, so the proposal for block 2 (from validator 2) can be crafted
+  let (network, events) = TestNetwork::new(1).await;
+
+  let mut block = TestBlock::new(2);
+  block.valid = Err(BlockError::Fatal(Some(7)));
+  let msg = signed(2, BlockNumber(2), RoundNumber(0), proposal(None, block)).await;
+  network.write().await[0].0.send(msg).await.unwrap();
+
+  // The code validate returned is passed along with the slash
+  sleep(Duration::from_secs(1)).await;
+  assert!(events.slashes.read().await.contains(&(2, SlashReason::InvalidBlock(Some(7)))));
+}
+
+#[tokio::test]
+async fn heartbeat() {
+  let heartbeat = Heartbeat::new(&TestSigner(1, 0), BlockNumber(2), now()).await.unwrap();
+  assert_eq!(heartbeat.sender, 1);
+  assert!(heartbeat.verify(&TestSignatureScheme::default()));
+
+  // Altering any field invalidates the signature
+  let mut altered = heartbeat.clone();
+  altered.number = BlockNumber(3);
+  assert!(!altered.verify(&TestSignatureScheme::default()));
+  let mut altered = heartbeat.clone();
+  altered.sender = 2;
+  assert!(!altered.verify(&TestSignatureScheme::default()));
+
+  // A heartbeat's signature can't be used for a consensus message
+  let vote = [(1u16, BlockNumber(2), RoundNumber(0)).encode(), prevote(None)].concat();
+  assert!(!TestSignatureScheme::default().verify(1, &vote, &heartbeat.sig));
+}
+
+#[tokio::test]
+async fn drain_on_shutdown() {
+  // Validator 2 proposes block 2, queueing its proposal upon creation
+  let (TendermintHandle { messages, machine, step, .. }, mut broadcast) =
+    lone_machine(2, Events::default(), now(), TestBlock::new(2)).await;
+
+  // Shut the machine down before it handles its queue, which should still handle and broadcast the
+  // proposal, then the prevote handling it queues
+  drop((messages, step));
+  machine.run().await;
+  let mut steps = vec![];
+  while let Ok(Some(msg)) = broadcast.try_next() {
+    assert_eq!(msg.block(), BlockNumber(2));
+    // The step is the variant index of the data, after the sender, block, and round
+    steps.push(msg.encode()[14]);
+  }
+  assert_eq!(steps, vec![0, 1]);
+}
+
+#[tokio::test]
+async fn shutdown_report() {
+  // Keep the step channel open, so only closing the message channel shuts the machine down
+  let (TendermintHandle { mut messages, machine, step: _step, .. }, _observed) =
+    lone_machine(0, Events::default(), now(), TestBlock::new(1)).await;
+
+  // Validator 2 proposes block 2, which we prevote for, then shut down mid-prevote
+  let block = TestBlock::new(2);
+  messages
+    .send(signed(2, BlockNumber(2), RoundNumber(0), proposal(None, block)).await)
+    .await
+    .unwrap();
+  drop(messages);
+
+  let report = machine.run().await;
+  assert_eq!(report.block, BlockNumber(2));
+  assert_eq!(report.round, RoundNumber(0));
+  assert_eq!(report.step, Step::Prevote);
+  assert_eq!(report.heard_from, vec![2]);
+  assert_eq!(report.locked, None);
+  assert_eq!(report.valid, None);
+}
+
+#[cfg(feature = "export")]
+#[tokio::test]
+async fn export_import() {
+  let last_time = now();
+  let block = TestBlock::new(1);
+  let msg = signed(2, BlockNumber(2), RoundNumber(0), proposal(None, block.clone())).await;
+  let mut msgs = vec![msg];
+  for sender in [0, 1, 2] {
+    let precommit = precommit(TestSigner(sender, 0), last_time + 5, block.id()).await;
+    msgs.push(signed(sender, BlockNumber(2), RoundNumber(0), precommit).await);
+  }
+
+  // Observers are used as they don't add messages of their own to the log
+  // The first receives every message, the second imports all but the last
+  let mut exported = vec![];
+  for imported in [0, msgs.len() - 1] {
+    let events = Events::default();
+    let (TendermintHandle { mut messages, mut machine, step: _step, .. }, _observed) =
+      lone_machine(4, events.clone(), last_time, TestBlock::new(1)).await;
+    machine.import_log(msgs[.. imported].to_vec());
+    tokio::task::spawn(machine.run());
+    for msg in &msgs[imported ..] {
+      messages.send(msg.clone()).await.unwrap();
+    }
+
+    // The log is exported once the next block starts, after round 0 ends
+    sleep(Duration::from_secs(6)).await;
+    assert_eq!(events.added_blocks.read().await[0].1, block.id());
+    exported.push(events.exported_logs.read().await[0].clone());
+  }
+
+  // Both exported the exact log of messages received, in order
+  assert_eq!(exported[0], (BlockNumber(2), msgs));
+  assert_eq!(exported[0], exported[1]);
+}